@@ -0,0 +1,164 @@
+use crate::camera::PerspectiveCamera;
+use crate::vec::Vec3;
+
+// the animatable parameters of a perspective camera at a point in time,
+// time is measured in frames
+#[derive(Clone, Copy, Debug)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+}
+
+impl CameraKeyframe {
+    pub fn new(time: f32, look_from: Vec3, look_at: Vec3, vfov: f32) -> Self {
+        Self {
+            time,
+            look_from,
+            look_at,
+            vfov,
+            aperture: 0.0,
+            focus_dist: (look_from - look_at).magnitude(),
+        }
+    }
+
+    pub fn with_focus(mut self, aperture: f32, focus_dist: f32) -> Self {
+        self.aperture = aperture;
+        self.focus_dist = focus_dist;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Interpolation {
+    Linear,
+    // passes through every key with a continuous velocity, the smooth choice
+    // for fly-throughs
+    CatmullRom,
+}
+
+// values blended between keys, kept apart from the keyframe so interpolation
+// doesn't have to know about time
+#[derive(Clone, Copy)]
+struct Params {
+    look_from: Vec3,
+    look_at: Vec3,
+    vfov: f32,
+    aperture: f32,
+    focus_dist: f32,
+}
+
+impl Params {
+    fn from_key(key: &CameraKeyframe) -> Self {
+        Self {
+            look_from: key.look_from,
+            look_at: key.look_at,
+            vfov: key.vfov,
+            aperture: key.aperture,
+            focus_dist: key.focus_dist,
+        }
+    }
+
+    // weighted sum of four sets of parameters
+    fn blend(params: [Self; 4], weights: [f32; 4]) -> Self {
+        let mut out = Self {
+            look_from: Vec3::new(0.0, 0.0, 0.0),
+            look_at: Vec3::new(0.0, 0.0, 0.0),
+            vfov: 0.0,
+            aperture: 0.0,
+            focus_dist: 0.0,
+        };
+        for (p, &w) in params.iter().zip(weights.iter()) {
+            out.look_from += w * p.look_from;
+            out.look_at += w * p.look_at;
+            out.vfov += w * p.vfov;
+            out.aperture += w * p.aperture;
+            out.focus_dist += w * p.focus_dist;
+        }
+
+        out
+    }
+}
+
+// keyframed camera, holds the camera at the first and last key outside of
+// their range
+pub struct CameraAnimation {
+    keys: Vec<CameraKeyframe>,
+    interpolation: Interpolation,
+    v_up: Vec3,
+    aspect: f32,
+}
+
+impl CameraAnimation {
+    pub fn new(v_up: Vec3, aspect: f32, interpolation: Interpolation) -> Self {
+        Self {
+            keys: vec![],
+            interpolation,
+            v_up,
+            aspect,
+        }
+    }
+
+    // keys may be pushed in any order
+    pub fn push(&mut self, key: CameraKeyframe) {
+        let index = self.keys.partition_point(|k| k.time <= key.time);
+        self.keys.insert(index, key);
+    }
+
+    pub fn camera_at(&self, time: f32) -> PerspectiveCamera {
+        assert!(!self.keys.is_empty(), "camera animation has no keys");
+        let last = self.keys.len() - 1;
+
+        // segment i runs from key i to key i + 1
+        let i = self
+            .keys
+            .partition_point(|k| k.time <= time)
+            .saturating_sub(1)
+            .min(last.saturating_sub(1));
+        let p = |i: usize| Params::from_key(&self.keys[i.min(last)]);
+
+        let params = if last == 0 {
+            p(0)
+        } else {
+            let span = self.keys[i + 1].time - self.keys[i].time;
+            let t = if span > 0.0 {
+                ((time - self.keys[i].time) / span).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+
+            match self.interpolation {
+                Interpolation::Linear => {
+                    Params::blend([p(i), p(i + 1), p(i), p(i)], [1.0 - t, t, 0.0, 0.0])
+                }
+                Interpolation::CatmullRom => {
+                    // the end keys stand in for the missing neighbors
+                    let t2 = t * t;
+                    let t3 = t2 * t;
+                    Params::blend(
+                        [p(i.saturating_sub(1)), p(i), p(i + 1), p(i + 2)],
+                        [
+                            0.5 * (-t3 + 2.0 * t2 - t),
+                            0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+                            0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+                            0.5 * (t3 - t2),
+                        ],
+                    )
+                }
+            }
+        };
+
+        PerspectiveCamera::new(
+            params.look_from,
+            params.look_at,
+            self.v_up,
+            params.vfov,
+            self.aspect,
+            params.aperture.max(0.0),
+            params.focus_dist,
+        )
+    }
+}
@@ -0,0 +1,140 @@
+use crate::hittable::Hittable;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vec::Vec3;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::ops::{Add, AddAssign, Mul};
+
+// arbitrary output variables, what the camera ray saw at its first hit. all
+// of them are zero where the ray missed everything
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Aovs {
+    // outward facing world space normal
+    pub normal: Vec3,
+    pub albedo: Vec3,
+    pub position: Vec3,
+    // distance along the camera ray, the same in every channel
+    pub depth: Vec3,
+    // texture coordinates in the red and green channels
+    pub uv: Vec3,
+    // ids as distinct colors, ready to key mattes from
+    pub material_id: Vec3,
+    pub object_id: Vec3,
+}
+
+impl Aovs {
+    pub const NAMES: [&'static str; 7] = [
+        "normal",
+        "albedo",
+        "position",
+        "depth",
+        "uv",
+        "material_id",
+        "object_id",
+    ];
+
+    // casts the camera ray and records what it hits first
+    pub fn first_hit(ray: &Ray, world: &dyn Hittable) -> Self {
+        stats::record(|stats| stats.rays += 1);
+        match world.hit(ray, 0.001, f32::MAX) {
+            Some(hit) => {
+                let depth = hit.t * ray.direction.magnitude();
+
+                Self {
+                    normal: hit.outward_normal().make_unit_vector(),
+                    albedo: hit.material.albedo(&hit),
+                    position: hit.point,
+                    depth: Vec3::new(depth, depth, depth),
+                    uv: Vec3::new(hit.u, hit.v, 0.0),
+                    material_id: id_color(hit.material_id),
+                    object_id: id_color(hit.object_id),
+                }
+            }
+            None => Self::default(),
+        }
+    }
+
+    // every variable in the order of NAMES
+    pub fn layers(&self) -> [Vec3; 7] {
+        [
+            self.normal,
+            self.albedo,
+            self.position,
+            self.depth,
+            self.uv,
+            self.material_id,
+            self.object_id,
+        ]
+    }
+}
+
+impl Add for Aovs {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            normal: self.normal + other.normal,
+            albedo: self.albedo + other.albedo,
+            position: self.position + other.position,
+            depth: self.depth + other.depth,
+            uv: self.uv + other.uv,
+            material_id: self.material_id + other.material_id,
+            object_id: self.object_id + other.object_id,
+        }
+    }
+}
+
+impl AddAssign for Aovs {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<f32> for Aovs {
+    type Output = Self;
+
+    fn mul(self, scale: f32) -> Self {
+        Self {
+            normal: self.normal * scale,
+            albedo: self.albedo * scale,
+            position: self.position * scale,
+            depth: self.depth * scale,
+            uv: self.uv * scale,
+            material_id: self.material_id * scale,
+            object_id: self.object_id * scale,
+        }
+    }
+}
+
+// scrambles an id into a bright color so neighboring ids look different
+pub fn id_color(id: usize) -> Vec3 {
+    let mut x = id as u64 ^ 0x9e37_79b9_7f4a_7c15;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+
+    let channel = |shift: u64| 0.2 + 0.8 * ((x >> shift) & 0xff) as f32 / 255.0;
+    Vec3::new(channel(0), channel(8), channel(16))
+}
+
+// writes unclamped linear values as a pfm float image, pixels are given
+// row by row from the top
+pub fn write_pfm(path: &str, cols: u32, rows: u32, pixels: &[Vec3]) -> std::io::Result<()> {
+    let mut file = File::create(path)?;
+    // a negative scale marks the data little endian
+    file.write_all(format!("PF\n{} {}\n-1.0\n", cols, rows).as_bytes())?;
+
+    // pfm stores the bottom row first
+    let mut bytes = Vec::with_capacity(pixels.len() * 12);
+    for row in pixels.chunks(cols as usize).rev() {
+        for pixel in row {
+            for value in [pixel.x, pixel.y, pixel.z].iter() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    file.write_all(&bytes)
+}
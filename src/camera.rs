@@ -1,9 +1,215 @@
+use crate::hittable::Hittable;
 use crate::ray::Ray;
 use crate::vec::Vec3;
 use rand::Rng;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
 
+// maps a point on the film, u and v in [0, 1] from the bottom left corner,
+// to the ray leaving the camera through it
+pub trait Camera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray;
+}
+
+// shape of the opening of the lens, which is the shape out of focus
+// highlights take
+#[derive(Clone, Debug)]
+pub enum Aperture {
+    Circle,
+    // regular polygon from straight diaphragm blades, rotation in degrees
+    Polygon { blades: u32, rotation: f32 },
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    // point on the aperture inside the unit disk
+    fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circle => random_point_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                let blades = (*blades).max(3);
+                let mut rng = rand::thread_rng();
+
+                // pick one of the equal triangles fanning out from the
+                // center, then a uniform point inside it
+                let step = 2.0 * std::f32::consts::PI / blades as f32;
+                let angle = rotation.to_radians() + step * rng.gen_range(0, blades) as f32;
+                let a = Vec3::new(angle.cos(), angle.sin(), 0.0);
+                let b = Vec3::new((angle + step).cos(), (angle + step).sin(), 0.0);
+                let (mut s, mut t) = (rng.gen::<f32>(), rng.gen::<f32>());
+                if s + t > 1.0 {
+                    s = 1.0 - s;
+                    t = 1.0 - t;
+                }
+
+                s * a + t * b
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+// image of the aperture stretched over the unit disk, brighter pixels let
+// more light through
 #[derive(Debug)]
-pub struct Camera {
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+    max: f32,
+}
+
+impl ApertureMask {
+    // data holds rows from the top, values in [0, 1]
+    pub fn new(width: usize, height: usize, data: Vec<f32>) -> Self {
+        assert_eq!(data.len(), width * height, "mask data does not match size");
+        let max = data.iter().cloned().fold(0.0, f32::max);
+        Self {
+            width,
+            height,
+            data,
+            max,
+        }
+    }
+
+    // reads the luminance of a P3 or P6 ppm image, like the renders we write
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        // the header is four whitespace separated tokens, comments aside
+        let mut tokens = vec![];
+        let mut pos = 0;
+        while tokens.len() < 4 {
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < bytes.len() && bytes[pos] == b'#' {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            let start = pos;
+            while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("truncated ppm header"));
+            }
+            tokens.push(String::from_utf8_lossy(&bytes[start..pos]).into_owned());
+        }
+        let size = |token: &str| {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid("bad size in ppm header"))
+        };
+        let (width, height, max_value) = (size(&tokens[1])?, size(&tokens[2])?, size(&tokens[3])?);
+        if max_value == 0 || max_value > 255 {
+            return Err(invalid("only 8 bit ppm images are supported"));
+        }
+
+        let samples: Vec<u8> = match tokens[0].as_str() {
+            "P6" => bytes.get(pos + 1..).unwrap_or(&[]).to_vec(),
+            "P3" => String::from_utf8_lossy(&bytes[pos..])
+                .split_whitespace()
+                .map(|s| s.parse::<u8>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid("bad sample in ppm body"))?,
+            _ => return Err(invalid("not a P3 or P6 ppm image")),
+        };
+        if samples.len() < width * height * 3 {
+            return Err(invalid("ppm body is shorter than its size"));
+        }
+
+        let data = samples
+            .chunks_exact(3)
+            .take(width * height)
+            .map(|rgb| {
+                let luminance =
+                    0.2126 * rgb[0] as f32 + 0.7152 * rgb[1] as f32 + 0.0722 * rgb[2] as f32;
+                luminance / max_value as f32
+            })
+            .collect();
+
+        Ok(Self::new(width, height, data))
+    }
+
+    // rejection samples the mask, falls back to the center of the lens if it
+    // is (nearly) black
+    fn sample(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        if self.max > 0.0 {
+            for _ in 0..1024 {
+                let x = rng.gen::<f32>();
+                let y = rng.gen::<f32>();
+                let column = ((x * self.width as f32) as usize).min(self.width - 1);
+                let row = (((1.0 - y) * self.height as f32) as usize).min(self.height - 1);
+                let point = Vec3::new(2.0 * x - 1.0, 2.0 * y - 1.0, 0.0);
+                if point.squared_mag() <= 1.0
+                    && rng.gen::<f32>() * self.max < self.data[row * self.width + column]
+                {
+                    return point;
+                }
+            }
+        }
+
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+}
+
+// right handed basis of a camera looking from look_from towards look_at, the
+// camera looks down -w
+fn look_basis(look_from: Vec3, look_at: Vec3, v_up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (look_from - look_at).make_unit_vector();
+    let u = (v_up.cross(&w)).make_unit_vector();
+    let v = w.cross(&u);
+
+    (u, v, w)
+}
+
+// physical description of a lens, lengths in millimeters with the scene
+// modeled in meters
+#[derive(Clone, Copy, Debug)]
+pub struct Lens {
+    pub focal_length: f32,
+    pub f_stop: f32,
+    pub sensor_height: f32,
+}
+
+impl Lens {
+    // on a full frame 36x24mm sensor
+    pub fn new(focal_length: f32, f_stop: f32) -> Self {
+        Self {
+            focal_length,
+            f_stop,
+            sensor_height: 24.0,
+        }
+    }
+
+    pub fn with_sensor_height(mut self, sensor_height: f32) -> Self {
+        self.sensor_height = sensor_height;
+        self
+    }
+
+    // vertical field of view in degrees
+    pub fn vfov(&self) -> f32 {
+        2.0 * (0.5 * self.sensor_height / self.focal_length)
+            .atan()
+            .to_degrees()
+    }
+
+    // diameter of the entrance pupil in scene units
+    pub fn aperture(&self) -> f32 {
+        self.focal_length / self.f_stop / 1000.0
+    }
+}
+
+// thin lens perspective camera
+#[derive(Clone, Debug)]
+pub struct PerspectiveCamera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
     pub horizontal: Vec3,
@@ -12,6 +218,11 @@ pub struct Camera {
     pub v: Vec3,
     pub w: Vec3,
     pub lens_radius: f32,
+    pub aperture: Aperture,
+    // how far the exit pupil clips the aperture towards the frame edges
+    pub cat_eye: f32,
+    // normal of the plane in focus when the lens is tilted
+    pub focus_normal: Option<Vec3>,
 }
 
 fn random_point_in_unit_disk() -> Vec3 {
@@ -25,7 +236,7 @@ fn random_point_in_unit_disk() -> Vec3 {
     point
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -41,9 +252,7 @@ impl Camera {
         let lens_radius = aperture / 2.0;
 
         let origin = look_from;
-        let w = (look_from - look_at).make_unit_vector();
-        let u = (v_up.cross(&w)).make_unit_vector();
-        let v = w.cross(&u);
+        let (u, v, w) = look_basis(look_from, look_at, v_up);
 
         let lower_left_corner =
             origin - half_width * focus_dist * u - half_height * focus_dist * v - focus_dist * w;
@@ -59,16 +268,401 @@ impl Camera {
             v,
             w,
             lens_radius,
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
+            focus_normal: None,
+        }
+    }
+
+    // camera set up from a physical lens instead of a field of view and
+    // aperture diameter
+    pub fn from_lens(
+        look_from: Vec3,
+        look_at: Vec3,
+        v_up: Vec3,
+        lens: Lens,
+        aspect: f32,
+        focus_dist: f32,
+    ) -> Self {
+        Self::new(
+            look_from,
+            look_at,
+            v_up,
+            lens.vfov(),
+            aspect,
+            lens.aperture(),
+            focus_dist,
+        )
+    }
+
+    // distance from the lens to the plane in focus
+    pub fn focus_dist(&self) -> f32 {
+        (self.origin - self.lower_left_corner).dot(&self.w)
+    }
+
+    // moves the plane in focus to whatever the pinhole ray through film
+    // position u, v hits first, keeps the focus if it hits nothing
+    pub fn focus_at(mut self, world: &dyn Hittable, u: f32, v: f32) -> Self {
+        let film = self.lower_left_corner + u * self.horizontal + v * self.vertical;
+        let ray = Ray::new(self.origin, film - self.origin);
+        if let Some(hit) = world.hit(&ray, 0.001, f32::MAX) {
+            let focus_dist = (self.origin - hit.point).dot(&self.w);
+            if focus_dist > 0.0 {
+                // the film sits on the plane in focus, scale it about the lens
+                let scale = focus_dist / self.focus_dist();
+                self.lower_left_corner =
+                    self.origin + (self.lower_left_corner - self.origin) * scale;
+                self.horizontal *= scale;
+                self.vertical *= scale;
+            }
+        }
+
+        self
+    }
+
+    // focuses on whatever is in the center of the frame
+    pub fn autofocus(self, world: &dyn Hittable) -> Self {
+        self.focus_at(world, 0.5, 0.5)
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // cat's eye vignetting, at 1 the aperture seen from a frame corner is
+    // clipped to a sliver
+    pub fn with_cat_eye(mut self, strength: f32) -> Self {
+        self.cat_eye = strength.clamp(0.0, 1.0);
+        self
+    }
+
+    // tilts the plane in focus, in degrees about the horizontal and vertical
+    // axes of the frame. it still passes through the center of the frame
+    pub fn with_tilt(mut self, tilt_x: f32, tilt_y: f32) -> Self {
+        let (sin_x, cos_x) = tilt_x.to_radians().sin_cos();
+        let (sin_y, cos_y) = tilt_y.to_radians().sin_cos();
+        let focus_normal = Vec3::new(sin_y * cos_x, -sin_x, cos_y * cos_x);
+        self.focus_normal =
+            Some(focus_normal.x * self.u + focus_normal.y * self.v + focus_normal.z * self.w);
+        self
+    }
+
+    // shifts the frame off the lens axis without turning the camera, in
+    // fractions of the frame width and height
+    pub fn with_shift(mut self, shift_x: f32, shift_y: f32) -> Self {
+        self.lower_left_corner += shift_x * self.horizontal + shift_y * self.vertical;
+        self
+    }
+
+    // point on the lens in units of lens radius for a ray through film
+    // position u, v
+    fn sample_lens(&self, u: f32, v: f32) -> Vec3 {
+        if self.cat_eye <= 0.0 {
+            return self.aperture.sample();
+        }
+
+        // off axis the exit pupil is seen as a disk shifted towards the
+        // center of the frame, only the overlap with the aperture lets light in
+        let pupil = self.cat_eye * Vec3::new(2.0 * u - 1.0, 2.0 * v - 1.0, 0.0);
+        for _ in 0..64 {
+            let point = self.aperture.sample();
+            if (point + pupil).squared_mag() <= 1.0 {
+                return point;
+            }
         }
+
+        -pupil * 0.5
+    }
+
+    // the view of one eye of a viewer with eyes ipd apart, the film stays
+    // where it is so both eyes converge on the focus plane
+    pub fn eye(&self, eye: Eye, ipd: f32) -> Self {
+        let mut camera = self.clone();
+        camera.origin += eye.side() * 0.5 * ipd * self.u;
+        camera
     }
+}
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = self.lens_radius * random_point_in_unit_disk();
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        let rd = self.lens_radius * self.sample_lens(u, v);
         let offset = self.u * rd.x + self.v * rd.y;
 
+        // where the ray through the center of the lens meets the plane in
+        // focus, which is the film plane unless the lens is tilted
+        let film = self.lower_left_corner + u * self.horizontal + v * self.vertical;
+        let focus = if let Some(focus_normal) = self.focus_normal {
+            let center =
+                self.lower_left_corner + 0.5 * self.horizontal + 0.5 * self.vertical - self.origin;
+            let direction = film - self.origin;
+            let t = center.dot(&focus_normal) / direction.dot(&focus_normal);
+            if !(t > 0.0 && t.is_finite()) {
+                // the plane in focus doesn't cross this ray, focus at infinity
+                return Ray::new(self.origin + offset, direction);
+            }
+            self.origin + t * direction
+        } else {
+            film
+        };
+
+        Ray::new(self.origin + offset, focus - self.origin - offset)
+    }
+}
+
+// parallel projection for elevations and plans, height is the extent of the
+// view in world units
+#[derive(Debug)]
+pub struct OrthographicCamera {
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+}
+
+impl OrthographicCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, height: f32, aspect: f32) -> Self {
+        let (u, v, w) = look_basis(look_from, look_at, v_up);
+        let horizontal = aspect * height * u;
+        let vertical = height * v;
+
+        Self {
+            lower_left_corner: look_from - 0.5 * horizontal - 0.5 * vertical,
+            horizontal,
+            vertical,
+            direction: -w,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        Ray::new(
+            self.lower_left_corner + u * self.horizontal + v * self.vertical,
+            self.direction,
+        )
+    }
+}
+
+// how a fisheye lens maps the angle off its axis to distance on the film
+#[derive(Clone, Copy, Debug)]
+pub enum FisheyeProjection {
+    // distance grows linearly with the angle
+    Equidistant,
+    // preserves solid angle, r = 2 f sin(theta / 2)
+    Equisolid,
+}
+
+impl FisheyeProjection {
+    // film radius of a ray theta off axis for a unit focal length
+    fn radius(self, theta: f32) -> f32 {
+        match self {
+            FisheyeProjection::Equidistant => theta,
+            FisheyeProjection::Equisolid => 2.0 * (theta / 2.0).sin(),
+        }
+    }
+
+    fn theta(self, radius: f32) -> f32 {
+        match self {
+            FisheyeProjection::Equidistant => radius,
+            FisheyeProjection::Equisolid => 2.0 * (radius / 2.0).clamp(-1.0, 1.0).asin(),
+        }
+    }
+}
+
+// full frame fisheye, fov is measured across the image diagonal so every
+// pixel sees the scene, up to 360 degrees
+#[derive(Debug)]
+pub struct FisheyeCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    aspect: f32,
+    projection: FisheyeProjection,
+    // film radius of the corners for a unit focal length
+    max_radius: f32,
+}
+
+impl FisheyeCamera {
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        v_up: Vec3,
+        fov: f32,
+        aspect: f32,
+        projection: FisheyeProjection,
+    ) -> Self {
+        let (u, v, w) = look_basis(look_from, look_at, v_up);
+        let half_fov = (fov * std::f32::consts::PI / 360.0).min(std::f32::consts::PI);
+
+        Self {
+            origin: look_from,
+            u,
+            v,
+            w,
+            aspect,
+            projection,
+            max_radius: projection.radius(half_fov),
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        // film position with the corners at unit distance from the center
+        let diagonal = (self.aspect * self.aspect + 1.0).sqrt();
+        let x = (2.0 * u - 1.0) * self.aspect / diagonal;
+        let y = (2.0 * v - 1.0) / diagonal;
+        let r = (x * x + y * y).sqrt();
+
+        let theta = self.projection.theta(r * self.max_radius);
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let (cos_phi, sin_phi) = if r > 0.0 { (x / r, y / r) } else { (1.0, 0.0) };
+
+        Ray::new(
+            self.origin,
+            sin_theta * cos_phi * self.u + sin_theta * sin_phi * self.v - cos_theta * self.w,
+        )
+    }
+}
+
+// latitude-longitude panorama covering the whole sphere, the center of the
+// image looks at look_at. use a 2:1 aspect for square pixels
+#[derive(Debug)]
+pub struct EquirectangularCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    // signed radius of the circle ray origins lie on, zero for a mono panorama
+    eye_offset: f32,
+}
+
+impl EquirectangularCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3) -> Self {
+        let (u, v, w) = look_basis(look_from, look_at, v_up);
+
+        Self {
+            origin: look_from,
+            u,
+            v,
+            w,
+            eye_offset: 0.0,
+        }
+    }
+
+    // omni-directional stereo panorama for one eye, every ray leaves from
+    // where that eye would be with the head turned to face it
+    pub fn ods(look_from: Vec3, look_at: Vec3, v_up: Vec3, eye: Eye, ipd: f32) -> Self {
+        let mut camera = Self::new(look_from, look_at, v_up);
+        camera.eye_offset = eye.side() * 0.5 * ipd;
+        camera
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        use std::f32::consts::PI;
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = (v - 0.5) * PI;
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let (sin_theta, cos_theta) = theta.sin_cos();
+
+        // to the right of the horizontal view direction
+        let right = cos_phi * self.u + sin_phi * self.w;
+
         Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset,
+            self.origin + self.eye_offset * right,
+            cos_theta * sin_phi * self.u + sin_theta * self.v - cos_theta * cos_phi * self.w,
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    // direction along the camera right vector the eye is offset in
+    fn side(self) -> f32 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+// how the two views of a stereo camera share one image
+#[derive(Clone, Copy, Debug)]
+pub enum StereoLayout {
+    // left eye in the left half
+    SideBySide,
+    // left eye in the top half
+    TopBottom,
+}
+
+// renders a stereo pair into a single image, each eye gets half of the film
+// so its camera should be set up for half the aspect ratio along the split
+pub struct StereoCamera {
+    left: Box<dyn Camera + Send + Sync>,
+    right: Box<dyn Camera + Send + Sync>,
+    layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(
+        left: Box<dyn Camera + Send + Sync>,
+        right: Box<dyn Camera + Send + Sync>,
+        layout: StereoLayout,
+    ) -> Self {
+        Self {
+            left,
+            right,
+            layout,
+        }
+    }
+
+    // side by side pair of eyes from a perspective camera placed between them
+    pub fn side_by_side(camera: &PerspectiveCamera, ipd: f32) -> Self {
+        Self::new(
+            Box::new(camera.eye(Eye::Left, ipd)),
+            Box::new(camera.eye(Eye::Right, ipd)),
+            StereoLayout::SideBySide,
         )
     }
+
+    // top bottom ods panorama, the usual format for vr video, use a 1:1 aspect
+    pub fn ods(look_from: Vec3, look_at: Vec3, v_up: Vec3, ipd: f32) -> Self {
+        Self::new(
+            Box::new(EquirectangularCamera::ods(
+                look_from,
+                look_at,
+                v_up,
+                Eye::Left,
+                ipd,
+            )),
+            Box::new(EquirectangularCamera::ods(
+                look_from,
+                look_at,
+                v_up,
+                Eye::Right,
+                ipd,
+            )),
+            StereoLayout::TopBottom,
+        )
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_ray(&self, u: f32, v: f32) -> Ray {
+        match self.layout {
+            StereoLayout::SideBySide if u < 0.5 => self.left.get_ray(2.0 * u, v),
+            StereoLayout::SideBySide => self.right.get_ray(2.0 * u - 1.0, v),
+            StereoLayout::TopBottom if v >= 0.5 => self.left.get_ray(u, 2.0 * v - 1.0),
+            StereoLayout::TopBottom => self.right.get_ray(u, 2.0 * v),
+        }
+    }
 }
@@ -0,0 +1,129 @@
+use crate::aov::Aovs;
+use crate::vec::Vec3;
+
+// edge avoiding a-trous wavelet filter, Dammertz et al. 2010. each pass
+// blurs with a sparser 5x5 kernel and the albedo, normal and depth buffers
+// keep the blur from crossing edges the noise doesn't explain
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    // how different neighbors may be before they stop counting, halved for
+    // color every pass as the noise goes down
+    pub sigma_color: f32,
+    pub sigma_normal: f32,
+    pub sigma_depth: f32,
+    pub sigma_albedo: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 4.0,
+            sigma_normal: 0.1,
+            sigma_depth: 0.1,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+impl Denoiser {
+    // color and aovs hold linear values row by row, returns the filtered color
+    pub fn denoise(&self, cols: usize, rows: usize, color: &[Vec3], aovs: &[Aovs]) -> Vec<Vec3> {
+        assert_eq!(color.len(), cols * rows, "color does not match image size");
+        assert_eq!(aovs.len(), cols * rows, "aovs do not match image size");
+
+        // filter the lighting alone so texture detail in the albedo survives
+        let albedo: Vec<Vec3> = aovs.iter().map(|aov| demodulation(aov.albedo)).collect();
+        let mut lighting: Vec<Vec3> = color
+            .iter()
+            .zip(albedo.iter())
+            .map(|(c, a)| c.make_comp_div(a))
+            .collect();
+
+        let mut sigma_color = self.sigma_color;
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            lighting = self.pass(cols, rows, step, sigma_color, &lighting, aovs);
+            sigma_color *= 0.5;
+        }
+
+        lighting
+            .iter()
+            .zip(albedo.iter())
+            .map(|(l, a)| l.make_comp_mul(a))
+            .collect()
+    }
+
+    fn pass(
+        &self,
+        cols: usize,
+        rows: usize,
+        step: i64,
+        sigma_color: f32,
+        lighting: &[Vec3],
+        aovs: &[Aovs],
+    ) -> Vec<Vec3> {
+        let mut out = Vec::with_capacity(lighting.len());
+        for y in 0..rows as i64 {
+            for x in 0..cols as i64 {
+                let center = (y as usize) * cols + x as usize;
+                let (c, p) = (lighting[center], &aovs[center]);
+
+                let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                let mut total = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let sy = y + (j as i64 - 2) * step;
+                    if sy < 0 || sy >= rows as i64 {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let sx = x + (i as i64 - 2) * step;
+                        if sx < 0 || sx >= cols as i64 {
+                            continue;
+                        }
+                        let sample = (sy as usize) * cols + sx as usize;
+                        let (q_color, q) = (lighting[sample], &aovs[sample]);
+
+                        let color_distance = (compress(c) - compress(q_color)).squared_mag();
+                        let normal_distance = (p.normal - q.normal).squared_mag();
+                        let albedo_distance = (p.albedo - q.albedo).squared_mag();
+                        // relative so distant surfaces aren't all edges
+                        let depth_distance = (p.depth.x - q.depth.x).abs() / p.depth.x.max(1e-3);
+
+                        let weight = kx
+                            * ky
+                            * (-color_distance / (sigma_color * sigma_color)).exp()
+                            * (-normal_distance / (self.sigma_normal * self.sigma_normal)).exp()
+                            * (-albedo_distance / (self.sigma_albedo * self.sigma_albedo)).exp()
+                            * (-depth_distance * depth_distance
+                                / (self.sigma_depth * self.sigma_depth))
+                                .exp();
+                        sum += q_color * weight;
+                        total += weight;
+                    }
+                }
+
+                out.push(if total > 0.0 { sum * (1.0 / total) } else { c });
+            }
+        }
+
+        out
+    }
+}
+
+// albedo divided out of the color before filtering, black albedo (misses,
+// emitters) is left alone
+fn demodulation(albedo: Vec3) -> Vec3 {
+    let channel = |a: f32| if a > 1e-3 { a } else { 1.0 };
+    Vec3::new(channel(albedo.x), channel(albedo.y), channel(albedo.z))
+}
+
+// color differences are compared after a reinhard curve so fireflies don't
+// dominate the edge stopping
+fn compress(color: Vec3) -> Vec3 {
+    let channel = |c: f32| c / (1.0 + c);
+    Vec3::new(channel(color.x), channel(color.y), channel(color.z))
+}
@@ -1,22 +1,35 @@
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::stats;
 use crate::vec::Vec3;
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub t: f32,
     pub u: f32,
     pub v: f32,
     pub point: Vec3,
+    // always faces against the incoming ray, front_face records whether that
+    // is the outward side of the surface
     pub normal: Vec3,
-    pub material: &'a Box<dyn Material + Send + Sync>,
+    pub front_face: bool,
+    // partial derivatives of point with respect to u and v, left at zero by
+    // surfaces without a parameterization
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub material: &'a (dyn Material + Send + Sync),
+    // ids set by the top level list that was hit, see HittableList::push
+    pub material_id: usize,
+    // index of the object in the top level list that was hit
+    pub object_id: usize,
 }
 
 impl<'a> HitRecord<'a> {
-    fn new(
+    pub(crate) fn new(
         t: f32,
         point: Vec3,
         normal: Vec3,
-        material: &'a Box<dyn Material + Send + Sync>,
+        material: &'a (dyn Material + Send + Sync),
     ) -> Self {
         Self {
             t,
@@ -24,13 +37,45 @@ impl<'a> HitRecord<'a> {
             v: 0.0,
             point,
             normal,
+            front_face: true,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
             material,
+            material_id: 0,
+            object_id: 0,
+        }
+    }
+
+    pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
+        self.front_face = ray.direction.dot(&outward_normal) < 0.0;
+        self.normal = if self.front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+    }
+
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
         }
     }
 }
 
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    // fraction of light making it along the ray between t_min and t_max,
+    // surfaces block it completely and volumes let some of it through
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        if self.hit(ray, t_min, t_max).is_some() {
+            0.0
+        } else {
+            1.0
+        }
+    }
 }
 
 pub fn get_sphere_uv(point: Vec3) -> (f32, f32) {
@@ -40,6 +85,23 @@ pub fn get_sphere_uv(point: Vec3) -> (f32, f32) {
     (1.0 - (phi + PI) / (2.0 * PI), (theta + PI / 2.0) / PI)
 }
 
+// dp/du and dp/dv for the parameterization of get_sphere_uv, offset is the
+// hit point relative to the center
+pub fn get_sphere_tangents(offset: Vec3) -> (Vec3, Vec3) {
+    use std::f32::consts::PI;
+    let rho = (offset.x * offset.x + offset.z * offset.z).sqrt();
+    if rho == 0.0 {
+        // u is degenerate at the poles
+        return (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+    }
+    let (cos_phi, sin_phi) = (offset.x / rho, offset.z / rho);
+
+    let tangent = 2.0 * PI * Vec3::new(offset.z, 0.0, -offset.x);
+    let bitangent = PI * Vec3::new(-offset.y * cos_phi, rho, -offset.y * sin_phi);
+
+    (tangent, bitangent)
+}
+
 pub struct Sphere {
     center: Vec3,
     radius: f32,
@@ -57,7 +119,8 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::record(|stats| stats.intersection_tests += 1);
         let oc = ray.origin - self.center;
         let a = ray.direction.squared_mag();
         let b = oc.dot(&ray.direction);
@@ -69,7 +132,7 @@ impl Hittable for Sphere {
                 0.0,
                 Vec3::new(0.0, 0.0, 0.0),
                 Vec3::new(0.0, 0.0, 0.0),
-                &self.material,
+                self.material.as_ref(),
             );
 
             // check - root
@@ -77,10 +140,13 @@ impl Hittable for Sphere {
             if temp < t_max && temp > t_min {
                 rec.t = temp;
                 rec.point = ray.point_at_parameter(rec.t);
-                rec.normal = (rec.point - self.center) * (1.0 / self.radius);
+                rec.set_face_normal(ray, (rec.point - self.center) * (1.0 / self.radius));
                 let (u, v) = get_sphere_uv((rec.point - self.center) * (1.0 / self.radius));
                 rec.u = u;
                 rec.v = v;
+                let (tangent, bitangent) = get_sphere_tangents(rec.point - self.center);
+                rec.tangent = tangent;
+                rec.bitangent = bitangent;
 
                 return Some(rec);
             }
@@ -90,10 +156,13 @@ impl Hittable for Sphere {
             if temp < t_max && temp > t_min {
                 rec.t = temp;
                 rec.point = ray.point_at_parameter(rec.t);
-                rec.normal = (rec.point - self.center) * (1.0 / self.radius);
+                rec.set_face_normal(ray, (rec.point - self.center) * (1.0 / self.radius));
                 let (u, v) = get_sphere_uv((rec.point - self.center) * (1.0 / self.radius));
                 rec.u = u;
                 rec.v = v;
+                let (tangent, bitangent) = get_sphere_tangents(rec.point - self.center);
+                rec.tangent = tangent;
+                rec.bitangent = bitangent;
                 return Some(rec);
             }
         }
@@ -101,32 +170,69 @@ impl Hittable for Sphere {
     }
 }
 
+#[derive(Default)]
 pub struct HittableList {
     list: Vec<Box<dyn Hittable + Send + Sync>>,
+    material_ids: Vec<usize>,
+    next_material_id: usize,
 }
 
 impl HittableList {
     pub fn new() -> Self {
-        Self { list: vec![] }
+        Self::default()
     }
 
+    // the item gets a material id of its own, ids count up from zero in the
+    // order they are handed out so they stay the same between runs
     pub fn push(&mut self, item: Box<dyn Hittable + Send + Sync>) {
+        let material_id = self.new_material_id();
+        self.push_with_material_id(item, material_id);
+    }
+
+    // reserves a material id for several items to share
+    pub fn new_material_id(&mut self) -> usize {
+        self.next_material_id += 1;
+        self.next_material_id - 1
+    }
+
+    // items pushed with the same id count as one material in the material
+    // id aov
+    pub fn push_with_material_id(
+        &mut self,
+        item: Box<dyn Hittable + Send + Sync>,
+        material_id: usize,
+    ) {
         self.list.push(item);
+        self.material_ids.push(material_id);
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut to_return: Option<HitRecord> = None;
         let mut closest_found = t_max;
 
-        for item in self.list.iter() {
-            if let Some(hit) = item.hit(ray, t_min, closest_found) {
+        for (index, item) in self.list.iter().enumerate() {
+            if let Some(mut hit) = item.hit(ray, t_min, closest_found) {
                 closest_found = hit.t;
+                hit.material_id = self.material_ids[index];
+                hit.object_id = index;
                 to_return = Some(hit);
             }
         }
 
         to_return
     }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let mut transmittance = 1.0;
+        for item in self.list.iter() {
+            transmittance *= item.transmittance(ray, t_min, t_max);
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+
+        transmittance
+    }
 }
@@ -0,0 +1,172 @@
+use crate::aov::id_color;
+use crate::hittable::Hittable;
+use crate::light::LightList;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vec::Vec3;
+use crate::{color, color_spectral, random_cosine_direction, white_furnace};
+
+use std::str::FromStr;
+
+// what gets computed for every camera ray. apart from the two path tracers
+// these are diagnostics that look at the first hit only and are cheap enough
+// for quick iteration on a scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    PathTracer,
+    // traces hero wavelengths instead of rgb, slower but exact for
+    // dispersion and spectral lights
+    Spectral,
+    // outward facing shading normal, including normal and bump maps, mapped
+    // from [-1, 1] to [0, 1]
+    Normals,
+    Uv,
+    // brighter is closer, falling off over tens of units
+    Distance,
+    MaterialId,
+    // intersection tests the camera ray needed, blue for few through red for
+    // many. without a bvh this is flat over the whole image
+    Cost,
+    WhiteFurnace,
+    // share of cosine weighted rays from the first hit that travel distance
+    // without hitting anything, white where the surface is fully open. for
+    // clay renders and baking occlusion
+    AmbientOcclusion { samples: u32, distance: f32 },
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 9] = [
+        Integrator::PathTracer,
+        Integrator::Spectral,
+        Integrator::Normals,
+        Integrator::Uv,
+        Integrator::Distance,
+        Integrator::MaterialId,
+        Integrator::Cost,
+        Integrator::WhiteFurnace,
+        Integrator::AmbientOcclusion {
+            samples: 16,
+            distance: 1.0,
+        },
+    ];
+
+    // what it is called on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Integrator::PathTracer => "path",
+            Integrator::Spectral => "spectral",
+            Integrator::Normals => "normals",
+            Integrator::Uv => "uv",
+            Integrator::Distance => "distance",
+            Integrator::MaterialId => "material-id",
+            Integrator::Cost => "cost",
+            Integrator::WhiteFurnace => "furnace",
+            Integrator::AmbientOcclusion { .. } => "ao",
+        }
+    }
+
+    // whether this is one of the first hit passes rather than a path tracer
+    pub fn is_debug(self) -> bool {
+        !matches!(self, Integrator::PathTracer | Integrator::Spectral)
+    }
+
+    // u picks the wavelengths for the spectral integrator
+    pub fn radiance(
+        self,
+        ray: Ray,
+        world: &dyn Hittable,
+        lights: &LightList,
+        has_light: bool,
+        u: f32,
+    ) -> Vec3 {
+        match self {
+            Integrator::PathTracer => color(ray, world, lights, 0, has_light),
+            Integrator::Spectral => color_spectral(ray, world, lights, has_light, u),
+            Integrator::WhiteFurnace => white_furnace(ray, world),
+            Integrator::Cost => {
+                stats::record(|stats| stats.rays += 1);
+                let before = stats::current().intersection_tests;
+                world.hit(&ray, 0.001, f32::MAX);
+                let tests = stats::current().intersection_tests - before;
+                heat(((1 + tests) as f32).log2() / 10.0)
+            }
+            _ => {
+                stats::record(|stats| stats.rays += 1);
+                let hit = match world.hit(&ray, 0.001, f32::MAX) {
+                    Some(hit) => hit,
+                    None => return Vec3::new(0.0, 0.0, 0.0),
+                };
+                match self {
+                    Integrator::Normals => {
+                        let normal = hit.material.shading_normal(&hit).make_unit_vector();
+                        let normal = if hit.front_face { normal } else { -normal };
+                        0.5 * (normal + Vec3::new(1.0, 1.0, 1.0))
+                    }
+                    Integrator::Uv => Vec3::new(hit.u, hit.v, 0.0),
+                    Integrator::AmbientOcclusion { samples, distance } => {
+                        let open =
+                            ambient_occlusion(&hit.point, &hit.normal, world, samples, distance);
+                        Vec3::new(open, open, open)
+                    }
+                    Integrator::Distance => {
+                        let falloff = (-hit.t * ray.direction.magnitude() / 10.0).exp();
+                        Vec3::new(falloff, falloff, falloff)
+                    }
+                    _ => id_color(hit.material_id),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Integrator::ALL
+            .iter()
+            .copied()
+            .find(|integrator| integrator.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Integrator::ALL.iter().map(|i| i.name()).collect();
+                format!(
+                    "unknown integrator {}, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// fraction of samples rays leaving point around normal that get further than
+// distance
+fn ambient_occlusion(
+    point: &Vec3,
+    normal: &Vec3,
+    world: &dyn Hittable,
+    samples: u32,
+    distance: f32,
+) -> f32 {
+    let basis = Onb::from_w(normal);
+    let mut open = 0;
+    for _ in 0..samples {
+        let ray = Ray::new(*point, basis.to_world(&random_cosine_direction()));
+        stats::record(|stats| stats.rays += 1);
+        if world.hit(&ray, 0.001, distance).is_none() {
+            open += 1;
+        }
+    }
+
+    open as f32 / samples.max(1) as f32
+}
+
+// blue through green to red for t in [0, 1]
+fn heat(t: f32) -> Vec3 {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Vec3::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+        Vec3::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+    }
+}
@@ -0,0 +1,309 @@
+use rand::Rng;
+
+pub mod animation;
+pub mod aov;
+pub mod camera;
+pub mod denoise;
+pub mod hittable;
+pub mod integrator;
+pub mod light;
+pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod onb;
+pub mod ray;
+pub mod render;
+pub mod spectrum;
+pub mod stats;
+pub mod texture;
+pub mod vec;
+pub mod volume;
+
+use hittable::HitRecord;
+use hittable::Hittable;
+use light::LightList;
+use medium::MediumEvent;
+use ray::Ray;
+use spectrum::{SampledSpectrum, SampledWavelengths};
+use stats::Termination;
+use vec::Vec3;
+
+pub fn color(
+    ray: Ray,
+    world: &dyn Hittable,
+    lights: &LightList,
+    depth: u32,
+    has_light: bool,
+) -> Vec3 {
+    let background = if has_light {
+        Background::Black
+    } else {
+        Background::Sky
+    };
+    trace(ray, world, lights, depth, background)
+}
+
+// the scene lit by nothing but a uniform white environment, energy
+// conserving materials that don't absorb should disappear into it
+pub fn white_furnace(ray: Ray, world: &dyn Hittable) -> Vec3 {
+    trace(ray, world, &LightList::new(), 0, Background::White)
+}
+
+// what rays leaving the scene see
+#[derive(Clone, Copy)]
+enum Background {
+    Black,
+    Sky,
+    White,
+}
+
+impl Background {
+    fn radiance(self, ray: &Ray) -> Vec3 {
+        match self {
+            Background::Black => Vec3::new(0.0, 0.0, 0.0),
+            Background::Sky => sky(ray),
+            Background::White => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+fn trace(
+    ray: Ray,
+    world: &dyn Hittable,
+    lights: &LightList,
+    depth: u32,
+    background: Background,
+) -> Vec3 {
+    stats::record(|stats| stats.rays += 1);
+    if let Some(hit) = world.hit(&ray, 0.001, f32::MAX) {
+        let (ray, hit, throughput) = match random_walk(ray, hit, world) {
+            Some(walk) => walk,
+            None => {
+                stats::record_path(Termination::Escape, depth + 1);
+                return Vec3::new(0.0, 0.0, 0.0);
+            }
+        };
+        if throughput.squared_mag() == 0.0 {
+            stats::record_path(Termination::Absorption, depth + 1);
+            return throughput;
+        }
+
+        let emitted = hit.material.emitted(&hit) + direct_lighting(&ray, &hit, world, lights);
+        if let Some((mut scattered, attenuation)) = hit.material.scatter(&ray, &hit) {
+            // keep carrying the wavelength a dispersive bounce narrowed us to
+            if scattered.wavelength.is_none() {
+                scattered.wavelength = ray.wavelength;
+            }
+            if depth >= 50 {
+                stats::record_path(Termination::Depth, depth + 1);
+                return throughput.make_comp_mul(&emitted);
+            }
+            throughput.make_comp_mul(
+                &(emitted
+                    + attenuation.make_comp_mul(&trace(
+                        scattered,
+                        world,
+                        lights,
+                        depth + 1,
+                        background,
+                    ))),
+            )
+        } else {
+            // absorbed
+            stats::record_path(Termination::Absorption, depth + 1);
+            throughput.make_comp_mul(&emitted)
+        }
+    } else {
+        stats::record_path(Termination::Escape, depth + 1);
+        background.radiance(&ray)
+    }
+}
+
+// longest random walk followed through a medium before giving up on the path
+const MAX_MEDIUM_STEPS: u32 = 256;
+
+// a path reaching the back face of a surface with an interior medium crossed
+// that medium to get there, walks it from collision to collision until it
+// reaches a surface again. returns the ray and hit of that last segment with
+// the throughput of the walk, or None if the path escaped or was lost
+fn random_walk<'a>(
+    ray: Ray,
+    hit: HitRecord<'a>,
+    world: &'a dyn Hittable,
+) -> Option<(Ray, HitRecord<'a>, Vec3)> {
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let medium = match hit.material.interior_medium() {
+        Some(medium) if !hit.front_face => *medium,
+        _ => return Some((ray, hit, throughput)),
+    };
+
+    let mut ray = ray;
+    let mut hit = hit;
+    for _ in 0..MAX_MEDIUM_STEPS {
+        match medium.sample(hit.t * ray.direction.magnitude()) {
+            MediumEvent::Pass { weight } => {
+                throughput.comp_mul(&weight);
+                return Some((ray, hit, throughput));
+            }
+            MediumEvent::Scatter { distance, weight } => {
+                throughput.comp_mul(&weight);
+                let direction = ray.direction.make_unit_vector();
+                let origin = ray.origin + distance * direction;
+                ray =
+                    Ray::with_wavelength(origin, medium.phase().sample(&direction), ray.wavelength);
+                stats::record(|stats| stats.rays += 1);
+                hit = world.hit(&ray, 0.001, f32::MAX)?;
+            }
+        }
+    }
+
+    None
+}
+
+// spectral counterpart of color(), traces the hero wavelengths picked by u
+// and returns the linear sRGB estimate at the film
+pub fn color_spectral(
+    ray: Ray,
+    world: &dyn Hittable,
+    lights: &LightList,
+    has_light: bool,
+    u: f32,
+) -> Vec3 {
+    let mut wavelengths = SampledWavelengths::sample(u);
+    let ray = Ray::with_wavelength(ray.origin, ray.direction, Some(wavelengths.hero()));
+    let radiance = trace_spectral(ray, world, lights, 0, has_light, &mut wavelengths);
+
+    wavelengths.to_rgb(&radiance)
+}
+
+fn trace_spectral(
+    ray: Ray,
+    world: &dyn Hittable,
+    lights: &LightList,
+    depth: u32,
+    has_light: bool,
+    wavelengths: &mut SampledWavelengths,
+) -> SampledSpectrum {
+    stats::record(|stats| stats.rays += 1);
+    if let Some(hit) = world.hit(&ray, 0.001, f32::MAX) {
+        let (ray, hit, throughput) = match random_walk(ray, hit, world) {
+            Some(walk) => walk,
+            None => {
+                stats::record_path(Termination::Escape, depth + 1);
+                return SampledSpectrum::new(0.0);
+            }
+        };
+        let throughput = SampledSpectrum::from_rgb_reflectance(&throughput, wavelengths);
+        if throughput.is_black() {
+            stats::record_path(Termination::Absorption, depth + 1);
+            return throughput;
+        }
+
+        // directions now depend on the hero wavelength alone
+        if hit.material.is_wavelength_dependent() {
+            wavelengths.terminate_secondary();
+        }
+
+        let mut emitted = hit.material.emitted_spectrum(&hit, wavelengths);
+        for_each_visible_light(&ray, &hit, world, lights, |f, radiance| {
+            emitted += SampledSpectrum::from_rgb_reflectance(&f, wavelengths)
+                * SampledSpectrum::from_rgb_illuminant(&radiance, wavelengths);
+        });
+
+        if let Some((mut scattered, attenuation)) = hit.material.scatter(&ray, &hit) {
+            scattered.wavelength = ray.wavelength;
+            if depth >= 50 {
+                stats::record_path(Termination::Depth, depth + 1);
+                return throughput * emitted;
+            }
+            let attenuation = SampledSpectrum::from_rgb_reflectance(&attenuation, wavelengths);
+            throughput
+                * (emitted
+                    + attenuation
+                        * trace_spectral(
+                            scattered,
+                            world,
+                            lights,
+                            depth + 1,
+                            has_light,
+                            wavelengths,
+                        ))
+        } else {
+            // absorbed
+            stats::record_path(Termination::Absorption, depth + 1);
+            throughput * emitted
+        }
+    } else {
+        stats::record_path(Termination::Escape, depth + 1);
+        if has_light {
+            SampledSpectrum::new(0.0)
+        } else {
+            SampledSpectrum::from_rgb_illuminant(&sky(&ray), wavelengths)
+        }
+    }
+}
+
+// pretend light exists
+fn sky(ray: &Ray) -> Vec3 {
+    let unit_direction = ray.direction.make_unit_vector();
+    let t = 0.5 * (unit_direction.y + 1.0);
+    (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+}
+
+// sums the contribution of every punctual light visible from the hit point
+fn direct_lighting(ray: &Ray, hit: &HitRecord, world: &dyn Hittable, lights: &LightList) -> Vec3 {
+    let mut sum = Vec3::new(0.0, 0.0, 0.0);
+    for_each_visible_light(ray, hit, world, lights, |f, radiance| {
+        sum += f.make_comp_mul(&radiance);
+    });
+
+    sum
+}
+
+// calls shade with the material response and the arriving radiance of every
+// punctual light that isn't fully shadowed
+fn for_each_visible_light<F: FnMut(Vec3, Vec3)>(
+    ray: &Ray,
+    hit: &HitRecord,
+    world: &dyn Hittable,
+    lights: &LightList,
+    mut shade: F,
+) {
+    for light in lights.iter() {
+        if let Some((direction, dist, radiance)) = light.illuminate(hit.point) {
+            let f = hit.material.eval(ray, hit, &direction);
+            if f.squared_mag() == 0.0 {
+                continue;
+            }
+
+            let shadow_ray = Ray::new(hit.point, direction);
+            stats::record(|stats| stats.rays += 1);
+            let transmittance = world.transmittance(&shadow_ray, 0.001, dist);
+            if transmittance > 0.0 {
+                shade(f, radiance * transmittance);
+            }
+        }
+    }
+}
+
+pub fn random_point_in_unit_sphere() -> Vec3 {
+    let mut point = Vec3::new(1.1, 1.1, 1.1);
+    let mut rng = rand::thread_rng();
+    while point.squared_mag() >= 1.0 {
+        point = 2.0 * Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
+            - Vec3::new(1.0, 1.0, 1.0);
+    }
+
+    point
+}
+
+// cosine weighted direction on the hemisphere around +z, pdf is z / pi
+pub fn random_cosine_direction() -> Vec3 {
+    let mut rng = rand::thread_rng();
+    let r1 = rng.gen::<f32>();
+    let r2 = rng.gen::<f32>();
+    let phi = 2.0 * std::f32::consts::PI * r1;
+    let r = r2.sqrt();
+
+    Vec3::new(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+}
@@ -0,0 +1,149 @@
+use crate::vec::Vec3;
+
+// punctual lights can't be hit by a bounced ray, they only contribute through
+// shadow rays cast by the integrator
+pub trait Light {
+    // returns the unit direction from point towards the light, the distance to
+    // the light and the radiance arriving at point
+    fn illuminate(&self, point: Vec3) -> Option<(Vec3, f32, Vec3)>;
+}
+
+pub struct PointLight {
+    position: Vec3,
+    intensity: Vec3,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn illuminate(&self, point: Vec3) -> Option<(Vec3, f32, Vec3)> {
+        let to_light = self.position - point;
+        let squared_dist = to_light.squared_mag();
+        if squared_dist == 0.0 {
+            return None;
+        }
+        let dist = squared_dist.sqrt();
+
+        // inverse square falloff
+        Some((
+            to_light * (1.0 / dist),
+            dist,
+            self.intensity * (1.0 / squared_dist),
+        ))
+    }
+}
+
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Vec3,
+    cos_inner: f32,
+    cos_outer: f32,
+}
+
+impl SpotLight {
+    // cone angles are in degrees measured from the spot direction, full
+    // intensity inside the inner cone fading to nothing at the outer cone
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        intensity: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        let to_cos = |angle: f32| (angle * std::f32::consts::PI / 180.0).cos();
+        let outer_angle = outer_angle.max(inner_angle);
+
+        Self {
+            position,
+            direction: direction.make_unit_vector(),
+            intensity,
+            cos_inner: to_cos(inner_angle),
+            cos_outer: to_cos(outer_angle),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta >= self.cos_inner {
+            1.0
+        } else if cos_theta <= self.cos_outer {
+            0.0
+        } else {
+            // smoothstep between the two cones
+            let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self, point: Vec3) -> Option<(Vec3, f32, Vec3)> {
+        let to_light = self.position - point;
+        let squared_dist = to_light.squared_mag();
+        if squared_dist == 0.0 {
+            return None;
+        }
+        let dist = squared_dist.sqrt();
+        let direction = to_light * (1.0 / dist);
+
+        let falloff = self.falloff(-direction.dot(&self.direction));
+        if falloff == 0.0 {
+            return None;
+        }
+
+        Some((direction, dist, self.intensity * (falloff / squared_dist)))
+    }
+}
+
+// infinitely far away light such as the sun, every point receives the same
+// irradiance from the same direction
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    // direction is the way the light travels, e.g. (0, -1, 0) for a noon sun
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        Self {
+            direction: direction.make_unit_vector(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self, _point: Vec3) -> Option<(Vec3, f32, Vec3)> {
+        Some((-self.direction, f32::MAX, self.irradiance))
+    }
+}
+
+#[derive(Default)]
+pub struct LightList {
+    list: Vec<Box<dyn Light + Send + Sync>>,
+}
+
+impl LightList {
+    pub fn new() -> Self {
+        Self { list: vec![] }
+    }
+
+    pub fn push(&mut self, item: Box<dyn Light + Send + Sync>) {
+        self.list.push(item);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Box<dyn Light + Send + Sync>> {
+        self.list.iter()
+    }
+}
@@ -1,54 +1,37 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::fs::File;
 use std::io::prelude::*;
-use std::sync::{Arc, Mutex};
-use std::thread;
-
-mod hittable;
-use hittable::Hittable;
-use hittable::HittableList;
-use hittable::Sphere;
-
-mod texture;
-use texture::CheckerTexture;
-use texture::ConstantTexture;
-
-mod camera;
-mod material;
-use material::Dielectric;
-use material::DiffuseLight;
-use material::Lambertian;
-use material::Metal;
-
-mod ray;
-use ray::Ray;
-mod vec;
-use vec::Vec3;
-
-struct Output {
-    rows: u32,
-    cols: u32,
-    colors: Arc<Mutex<Vec<Color>>>,
-}
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-impl Output {
-    fn write(&self) -> std::io::Result<()> {
-        let mut file = File::create("out.ppm")?;
+use raytracing_in_one_weekend::animation::{CameraAnimation, CameraKeyframe, Interpolation};
+use raytracing_in_one_weekend::aov::{write_pfm, Aovs};
+use raytracing_in_one_weekend::camera::{Camera, PerspectiveCamera};
+use raytracing_in_one_weekend::denoise::Denoiser;
+use raytracing_in_one_weekend::hittable::Hittable;
+use raytracing_in_one_weekend::integrator::Integrator;
+use raytracing_in_one_weekend::render::{render, Framebuffer, Progress, RenderSettings};
 
-        let header = format!("P3\n{} {}\n255\n", self.cols, self.rows);
+use raytracing_in_one_weekend::hittable::HittableList;
+use raytracing_in_one_weekend::hittable::Sphere;
 
-        file.write_all(header.as_bytes())?;
+use raytracing_in_one_weekend::texture::CheckerTexture;
+use raytracing_in_one_weekend::texture::ConstantTexture;
 
-        self.colors.lock().unwrap().iter().for_each(|color| {
-            let row = format!("{} {} {}\n", color.r, color.g, color.b);
-            file.write_all(row.as_bytes()).expect("color machine broke");
-        });
+use raytracing_in_one_weekend::light::DirectionalLight;
+use raytracing_in_one_weekend::light::LightList;
+use raytracing_in_one_weekend::light::PointLight;
+use raytracing_in_one_weekend::light::SpotLight;
 
-        Ok(())
-    }
-}
+use raytracing_in_one_weekend::material::Dielectric;
+use raytracing_in_one_weekend::material::DiffuseLight;
+use raytracing_in_one_weekend::material::Lambertian;
+use raytracing_in_one_weekend::material::Metal;
+
+use raytracing_in_one_weekend::vec::Vec3;
 
-#[derive(Clone)]
 struct Color {
     r: u8,
     g: u8,
@@ -70,45 +53,13 @@ impl Color {
     }
 }
 
-fn color(ray: Ray, world: Box<&dyn Hittable>, depth: u32, has_light: bool) -> Vec3 {
-    if let Some(hit) = world.hit(&ray, 0.001, std::f32::MAX) {
-        let emitted = hit.material.emitted(hit.u, hit.v, hit.point);
-        if let Some((scattered, attenuation)) = hit.material.scatter(&ray, &hit) {
-            if depth >= 50 {
-                return emitted;
-            }
-            return emitted
-                + attenuation.make_comp_mul(&color(scattered, world, depth + 1, has_light));
-        } else {
-            // absorbed
-            return emitted;
-        }
-    } else {
-        if has_light {
-            Vec3::new(0.0, 0.0, 0.0)
-        } else {
-            // pretend light exists
-            let unit_direction = ray.direction.make_unit_vector();
-            let t = 0.5 * (unit_direction.y + 1.0);
-            (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
-        }
-    }
-}
-
-fn random_point_in_unit_sphere() -> Vec3 {
-    let mut point = Vec3::new(1.1, 1.1, 1.1);
-    let mut rng = rand::thread_rng();
-    while point.squared_mag() >= 1.0 {
-        point = 2.0 * Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>())
-            - Vec3::new(1.0, 1.0, 1.0);
-    }
-
-    point
-}
-
-fn _sphere_cube_scene() -> HittableList {
+// random scenes are drawn from a seed so the same one can be built again to
+// resume a render
+fn _sphere_cube_scene(seed: u64) -> HittableList {
     let mut list = HittableList::new();
-    let mut rng = rand::thread_rng();
+    let mut rng = StdRng::seed_from_u64(seed);
+    // every glass sphere is the same material
+    let glass = list.new_material_id();
     list.push(Box::new(Sphere::new(
         Vec3::new(0.0, -1000.0, -0.0),
         1000.0,
@@ -150,11 +101,10 @@ fn _sphere_cube_scene() -> HittableList {
                     )));
                 } else {
                     // glass
-                    list.push(Box::new(Sphere::new(
-                        center,
-                        0.5,
-                        Box::new(Dielectric::new(1.5)),
-                    )));
+                    list.push_with_material_id(
+                        Box::new(Sphere::new(center, 0.5, Box::new(Dielectric::new(1.5)))),
+                        glass,
+                    );
                 }
             }
         }
@@ -191,8 +141,7 @@ fn light_scene() -> HittableList {
     list
 }
 
-fn _random_scene() -> HittableList {
-    let mut rng = rand::thread_rng();
+fn _punctual_light_scene() -> (HittableList, LightList) {
     let mut list = HittableList::new();
     list.push(Box::new(Sphere::new(
         Vec3::new(0.0, -1000.0, -0.0),
@@ -203,6 +152,54 @@ fn _random_scene() -> HittableList {
         )))),
     )));
 
+    list.push(Box::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        Box::new(Lambertian::new(Box::new(ConstantTexture::new(
+            (0.4, 0.2, 0.1).into(),
+        )))),
+    )));
+
+    list.push(Box::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 2.5),
+        1.0,
+        Box::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.1)),
+    )));
+
+    let mut lights = LightList::new();
+    lights.push(Box::new(PointLight::new(
+        Vec3::new(4.0, 4.0, -2.0),
+        (20.0, 18.0, 14.0).into(),
+    )));
+    lights.push(Box::new(SpotLight::new(
+        Vec3::new(0.0, 6.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        (60.0, 60.0, 80.0).into(),
+        15.0,
+        25.0,
+    )));
+    lights.push(Box::new(DirectionalLight::new(
+        Vec3::new(-1.0, -2.0, -1.0),
+        (0.5, 0.45, 0.4).into(),
+    )));
+
+    (list, lights)
+}
+
+fn _random_scene(seed: u64) -> HittableList {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut list = HittableList::new();
+    // every glass sphere is the same material
+    let glass = list.new_material_id();
+    list.push(Box::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, -0.0),
+        1000.0,
+        Box::new(Lambertian::new(Box::new(CheckerTexture::new(
+            Box::new(ConstantTexture::new((0.2, 0.3, 0.1).into())),
+            Box::new(ConstantTexture::new((0.9, 0.9, 0.9).into())),
+        )))),
+    )));
+
     for a in -11..11 {
         for b in -11..11 {
             let choose_mat = rng.gen::<f32>();
@@ -241,11 +238,10 @@ fn _random_scene() -> HittableList {
                 )));
             } else {
                 // glass
-                list.push(Box::new(Sphere::new(
-                    center,
-                    0.2,
-                    Box::new(Dielectric::new(1.5)),
-                )));
+                list.push_with_material_id(
+                    Box::new(Sphere::new(center, 0.2, Box::new(Dielectric::new(1.5)))),
+                    glass,
+                );
             }
         }
     }
@@ -274,13 +270,17 @@ fn _random_scene() -> HittableList {
     )));
     */
 
-    list.push(Box::new(Sphere::new(
-        Vec3::new(0.0, 1.0, 0.0),
-        1.0,
-        Box::new(Dielectric::new(1.5)),
-    )));
+    list.push_with_material_id(
+        Box::new(Sphere::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            Box::new(Dielectric::new(1.5)),
+        )),
+        glass,
+    );
     /*
-     * this makes glass sphere into a hollow bubble
+     * this makes glass sphere into a hollow bubble, with an absorbing
+     * Dielectric only the shell between the two surfaces tints the light
     list.push(Box::new(Sphere::new(
         Vec3::new(0.0, 1.0, 0.0),
         -0.99,
@@ -290,95 +290,370 @@ fn _random_scene() -> HittableList {
     list
 }
 
-struct OrderedColorVec {
-    index: u32,
-    colors: Arc<Mutex<Vec<Color>>>,
+// what gets written for every image besides the beauty pass
+struct OutputSettings {
+    // also write the first hit buffers next to the image
+    aovs: bool,
+    // filter the image guided by the aovs, makes low sample previews usable
+    denoise: bool,
+    // rewrite the image every this many passes and/or this often while it
+    // renders
+    snapshot_passes: Option<u32>,
+    snapshot_interval: Option<Duration>,
+    // save the accumulated samples to name.checkpoint this often, a render
+    // finding its checkpoint on start resumes from it
+    checkpoint_interval: Option<Duration>,
+    // write the render statistics to name_stats.json as well as printing them
+    stats_json: bool,
+}
+
+// command line options, everything else is set in main
+struct Args {
+    integrator: Integrator,
+    samples: Option<u32>,
+    ao_samples: Option<u32>,
+    ao_distance: Option<f32>,
+    // focus on whatever is under the center of the frame
+    autofocus: bool,
+    aovs: bool,
+    denoise: bool,
+    stats_json: bool,
+}
+
+const USAGE: &str = "usage: raytracing_in_one_weekend [--integrator NAME] [--samples N] \
+                     [--ao-samples N] [--ao-distance D] [--autofocus] [--aovs] [--denoise] \
+                     [--stats-json]";
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        integrator: Integrator::PathTracer,
+        samples: None,
+        ao_samples: None,
+        ao_distance: None,
+        autofocus: false,
+        aovs: false,
+        denoise: false,
+        stats_json: false,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--integrator" => args.integrator = value()?.parse()?,
+            "--samples" => {
+                let samples = value()?;
+                args.samples = Some(
+                    samples
+                        .parse()
+                        .map_err(|_| format!("invalid sample count {}", samples))?,
+                );
+            }
+            "--ao-samples" => {
+                let samples = value()?;
+                args.ao_samples = Some(
+                    samples
+                        .parse()
+                        .map_err(|_| format!("invalid occlusion sample count {}", samples))?,
+                );
+            }
+            "--ao-distance" => {
+                let distance = value()?;
+                args.ao_distance = Some(
+                    distance
+                        .parse()
+                        .map_err(|_| format!("invalid occlusion distance {}", distance))?,
+                );
+            }
+            "--autofocus" => args.autofocus = true,
+            "--aovs" => args.aovs = true,
+            "--denoise" => args.denoise = true,
+            "--stats-json" => args.stats_json = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                let names: Vec<_> = Integrator::ALL.iter().map(|i| i.name()).collect();
+                println!("integrators: {}", names.join(", "));
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    if args.ao_samples == Some(0) {
+        return Err("--ao-samples needs at least one sample".to_string());
+    }
+    if args
+        .ao_distance
+        .is_some_and(|d| !(d > 0.0 && d.is_finite()))
+    {
+        return Err("--ao-distance needs a positive distance".to_string());
+    }
+    match &mut args.integrator {
+        Integrator::AmbientOcclusion { samples, distance } => {
+            *samples = args.ao_samples.unwrap_or(*samples);
+            *distance = args.ao_distance.unwrap_or(*distance);
+        }
+        _ if args.ao_samples.is_some() || args.ao_distance.is_some() => {
+            return Err("--ao-samples and --ao-distance need --integrator ao".to_string());
+        }
+        _ => {}
+    }
+
+    Ok(args)
 }
 
 fn main() -> std::io::Result<()> {
-    let out = Arc::new(Output {
-        rows: 800,
-        cols: 1200,
-        colors: Arc::new(Mutex::new(vec![])),
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        std::process::exit(2);
     });
-    let num_samples = 1_000;
+
+    let mut settings = RenderSettings::new(1200, 800, 1_000);
+    settings.has_light = true;
+    settings.integrator = args.integrator;
+    // the diagnostics only need enough samples to antialias
+    if args.integrator.is_debug() {
+        settings.samples = 16;
+    }
+    if let Some(samples) = args.samples {
+        settings.samples = samples;
+    }
+    // settings.time_limit = Some(Duration::from_secs(10 * 60));
+    let output = OutputSettings {
+        aovs: args.aovs,
+        denoise: args.denoise,
+        snapshot_passes: None,
+        snapshot_interval: Some(Duration::from_secs(30)),
+        checkpoint_interval: Some(Duration::from_secs(5 * 60)),
+        stats_json: args.stats_json,
+    };
+    settings.aovs = output.aovs || output.denoise;
 
     let look_from = Vec3::new(13.0, 2.0, 3.0);
     let look_at = Vec3::new(0.0, 0.0, 0.0);
     let dist_to_focus = (look_from - look_at).magnitude();
     let aperature = 0.1;
+    let aspect = settings.cols as f32 / settings.rows as f32;
+
+    // the scene is built once and shared by every frame
+    // the name goes into checkpoints, so it has to change with the seed
+    // let (world, scene) = (_random_scene(1), "random 1");
+    let (world, scene) = (light_scene(), "light");
+    let lights = LightList::new();
+
+    // Some(1..=48) renders a turntable into out_0001.ppm, out_0002.ppm, ...
+    let frames: Option<RangeInclusive<u32>> = None;
+    if let Some(frames) = frames {
+        let mut animation =
+            CameraAnimation::new(Vec3::new(0.0, 1.0, 0.0), aspect, Interpolation::CatmullRom);
+        let turns = 4;
+        for key in 0..=turns {
+            let angle = 2.0 * std::f32::consts::PI * key as f32 / turns as f32;
+            let time = *frames.start() as f32
+                + (frames.end() - frames.start()) as f32 * key as f32 / turns as f32;
+            let radius = dist_to_focus;
+            let look_from = Vec3::new(radius * angle.cos(), 2.0, radius * angle.sin());
+            animation.push(
+                CameraKeyframe::new(time, look_from, look_at, 20.0)
+                    .with_focus(aperature, dist_to_focus),
+            );
+        }
+
+        for frame in frames {
+            println!("frame: {:?}", frame);
+            let camera = animation.camera_at(frame as f32);
+            let name = format!("out_{:04}", frame);
+            render_image(&name, scene, &camera, &world, &lights, &settings, &output)?;
+        }
+
+        return Ok(());
+    }
 
-    let camera = Arc::new(camera::Camera::new(
+    let mut camera = PerspectiveCamera::new(
         look_from,
         look_at,
         Vec3::new(0.0, 1.0, 0.0),
         20.0,
-        out.cols as f32 / out.rows as f32,
+        aspect,
         aperature,
         dist_to_focus,
-    ));
-
-    // let world = Arc::new(random_scene());
-    let world = Arc::new(light_scene());
-    let has_light = true;
-
-    let mut threads = vec![];
-    let thread_count = 16;
-    let color_vecs: Arc<Mutex<Vec<OrderedColorVec>>> = Arc::new(Mutex::new(vec![]));
-
-    for i in 0..thread_count {
-        let world = Arc::clone(&world);
-        let camera = Arc::clone(&camera);
-        let out = Arc::clone(&out);
-        let colors = OrderedColorVec {
-            index: i,
-            colors: Arc::new(Mutex::new(vec![])),
-        };
-        let color_vecs = Arc::clone(&color_vecs);
-        threads.push(thread::spawn(move || {
-            let mut rng = rand::thread_rng();
-            let start_row = i * out.rows / thread_count;
-            let end_row = start_row + out.rows / thread_count;
-
-            for j in start_row..end_row {
-                println!("row: {:?}", j);
-                for i in 0..out.cols {
-                    let mut sampled_color_sum = Vec3::new(0.0, 0.0, 0.0);
-
-                    for _ in 0..num_samples {
-                        let u = (i as f32 + rng.gen::<f32>()) / out.cols as f32;
-                        let v = (out.rows as f32 - (j as f32 + rng.gen::<f32>())) / out.rows as f32;
-                        let ray = camera.get_ray(u, v);
-                        sampled_color_sum += color(ray, Box::new(&*world), 0, has_light)
-                    }
-
-                    let unsum = sampled_color_sum * (1.0 / num_samples as f32);
-
-                    let color = Color::from_normalized_vec3(unsum.gamma_two());
-                    colors.colors.lock().unwrap().push(color);
+    );
+    if args.autofocus {
+        camera = camera.autofocus(&world);
+    }
+
+    // anything but the path tracer gets its own image
+    let name = match args.integrator {
+        Integrator::PathTracer => "out".to_string(),
+        integrator => format!("out_{}", integrator.name()),
+    };
+    render_image(&name, scene, &camera, &world, &lights, &settings, &output)
+}
+
+// renders name.ppm progressively, rewriting it whenever a snapshot is due
+// scene names the scene for checkpoints, including the seed of random ones
+fn render_image(
+    name: &str,
+    scene: &str,
+    camera: &(dyn Camera + Sync),
+    world: &(dyn Hittable + Sync),
+    lights: &LightList,
+    settings: &RenderSettings,
+    output: &OutputSettings,
+) -> std::io::Result<()> {
+    let path = format!("{}.ppm", name);
+    // named after the integrator too so one never picks up another's samples
+    let checkpoint = format!("{}.{}.checkpoint", name, settings.integrator.name());
+    // the scene and camera are built in code, so any change to them means a
+    // different binary. only worked out when there are checkpoints to match
+    let fingerprint = match output.checkpoint_interval {
+        Some(_) => Some(
+            settings
+                .fingerprint()
+                .with_bytes(scene.as_bytes())
+                .with_bytes(&std::fs::read(std::env::current_exe()?)?),
+        ),
+        None => None,
+    };
+    let resumed = fingerprint.and_then(|fingerprint| {
+        match Framebuffer::load_checkpoint(&checkpoint, fingerprint) {
+            Ok(framebuffer) => {
+                println!("resuming {} at {} samples", name, framebuffer.samples());
+                Some(framebuffer)
+            }
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    println!("not resuming from {}: {}", checkpoint, err);
                 }
+                None
+            }
+        }
+    });
+    let mut framebuffer = resumed.unwrap_or_else(|| Framebuffer::new(settings.cols, settings.rows));
+
+    let mut passes = 0;
+    let mut last_snapshot = Instant::now();
+    let mut last_checkpoint = Instant::now();
+    let mut result = Ok(());
+    let stats = render(
+        &mut framebuffer,
+        camera,
+        world,
+        lights,
+        settings,
+        |framebuffer| {
+            passes += 1;
+
+            let due = output.snapshot_passes.is_some_and(|n| passes % n == 0)
+                || output
+                    .snapshot_interval
+                    .is_some_and(|interval| last_snapshot.elapsed() >= interval);
+            if due && result.is_ok() {
+                result = write_image(&path, framebuffer, output);
+                last_snapshot = Instant::now();
             }
 
-            println!("thread done");
-            color_vecs.lock().unwrap().push(colors);
-        }));
+            let checkpoint_due = output
+                .checkpoint_interval
+                .is_some_and(|interval| last_checkpoint.elapsed() >= interval);
+            if let Some(fingerprint) = fingerprint.filter(|_| checkpoint_due && result.is_ok()) {
+                result = framebuffer.save_checkpoint(&checkpoint, fingerprint);
+                last_checkpoint = Instant::now();
+            }
+        },
+        print_progress,
+    );
+    println!();
+    print!("{}", stats);
+    result?;
+    if output.stats_json {
+        std::fs::write(format!("{}_stats.json", name), stats.to_json())?;
     }
 
-    for t in threads {
-        t.join().unwrap();
+    // a render stopped by the time limit keeps its checkpoint to go on from
+    if framebuffer.samples() >= settings.samples {
+        if Path::new(&checkpoint).exists() {
+            std::fs::remove_file(&checkpoint)?;
+        }
+    } else if let Some(fingerprint) = fingerprint {
+        framebuffer.save_checkpoint(&checkpoint, fingerprint)?;
     }
 
-    color_vecs
-        .lock()
-        .unwrap()
-        .sort_by(|a, b| a.index.cmp(&b.index));
+    write_image(&path, &framebuffer, output)?;
+    if output.aovs {
+        write_aovs(name, &framebuffer)?;
+    }
 
-    for color_vec in color_vecs.lock().unwrap().iter() {
-        println!("adding index: {:?} colors", color_vec.index);
-        for color in color_vec.colors.lock().unwrap().iter() {
-            out.colors.lock().unwrap().push(color.clone());
-        }
+    Ok(())
+}
+
+// keeps rewriting a single status line
+fn print_progress(progress: &Progress) {
+    let eta = match progress.eta() {
+        Some(eta) => format_duration(eta),
+        None => "-".to_string(),
+    };
+    print!(
+        "\r{:5.1}% | {:.2} Mrays/s | elapsed {} | eta {}   ",
+        100.0 * progress.fraction(),
+        progress.rays_per_second() / 1e6,
+        format_duration(progress.elapsed),
+        eta
+    );
+    std::io::stdout().flush().ok();
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn write_image(
+    path: &str,
+    framebuffer: &Framebuffer,
+    output: &OutputSettings,
+) -> std::io::Result<()> {
+    let mut colors = framebuffer.color();
+    if output.denoise {
+        colors = Denoiser::default().denoise(
+            framebuffer.cols as usize,
+            framebuffer.rows as usize,
+            &colors,
+            &framebuffer.aovs(),
+        );
+    }
+
+    let mut file = File::create(path)?;
+
+    let header = format!("P3\n{} {}\n255\n", framebuffer.cols, framebuffer.rows);
+
+    file.write_all(header.as_bytes())?;
+
+    colors.iter().for_each(|radiance| {
+        let color = Color::from_normalized_vec3(radiance.gamma_two());
+        let row = format!("{} {} {}\n", color.r, color.g, color.b);
+        file.write_all(row.as_bytes()).expect("color machine broke");
+    });
+
+    Ok(())
+}
+
+// one float image per aov, e.g. out_normal.pfm for name out
+fn write_aovs(name: &str, framebuffer: &Framebuffer) -> std::io::Result<()> {
+    let aovs = framebuffer.aovs();
+    for (layer, layer_name) in Aovs::NAMES.iter().enumerate() {
+        let pixels: Vec<Vec3> = aovs.iter().map(|aov| aov.layers()[layer]).collect();
+        write_pfm(
+            &format!("{}_{}.pfm", name, layer_name),
+            framebuffer.cols,
+            framebuffer.rows,
+            &pixels,
+        )?;
     }
 
-    out.write()
+    Ok(())
 }
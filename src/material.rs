@@ -1,6 +1,15 @@
 use crate::hittable::HitRecord;
+use crate::medium::HomogeneousMedium;
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, reflect, refract, TrowbridgeReitz};
+use crate::onb::Onb;
+use crate::random_cosine_direction;
 use crate::random_point_in_unit_sphere;
 use crate::ray::Ray;
+use crate::spectrum::{
+    sample_wavelength, thin_film_reflectance, wavelength_to_rgb, Illuminant, Ior, SampledSpectrum,
+    SampledWavelengths,
+};
+use crate::texture::ConstantTexture;
 use crate::texture::Texture;
 use crate::vec::Vec3;
 
@@ -9,9 +18,43 @@ use rand::Rng;
 pub trait Material {
     // returns a scattered ray and an attenuation factor
     fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)>;
-    fn emitted(&self, _u: f32, _v: f32, _point: Vec3) -> Vec3 {
+    // returns the bsdf times the cosine term for light arriving from the unit
+    // direction, only needed for materials that can be lit by shadow rays
+    fn eval(&self, _ray: &Ray, _hit: &HitRecord, _direction: &Vec3) -> Vec3 {
         (0.0, 0.0, 0.0).into()
     }
+    fn emitted(&self, _hit: &HitRecord) -> Vec3 {
+        (0.0, 0.0, 0.0).into()
+    }
+    // emission for the spectral integrator, by default the rgb emission
+    // upsampled as an illuminant
+    fn emitted_spectrum(
+        &self,
+        hit: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        SampledSpectrum::from_rgb_illuminant(&self.emitted(hit), wavelengths)
+    }
+    // whether scattering depends on the wavelength of the path, the spectral
+    // integrator can then only follow one wavelength through the bounce
+    fn is_wavelength_dependent(&self) -> bool {
+        false
+    }
+    // medium filling the inside of a closed surface, the integrator random
+    // walks through it whenever a path reaches one of its back faces
+    fn interior_medium(&self) -> Option<&HomogeneousMedium> {
+        None
+    }
+    // overall color of the surface for the albedo aov, clear and emissive
+    // materials count as white
+    fn albedo(&self, _hit: &HitRecord) -> Vec3 {
+        (1.0, 1.0, 1.0).into()
+    }
+    // normal the surface is shaded with, facing the incoming ray like
+    // hit.normal. differs from it only where a map perturbs it
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        hit.normal
+    }
 }
 
 pub struct Lambertian {
@@ -32,6 +75,19 @@ impl Material for Lambertian {
 
         Some((scattered, attenuation))
     }
+
+    fn eval(&self, _ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        let cosine = hit.normal.dot(direction);
+        if cosine <= 0.0 {
+            return (0.0, 0.0, 0.0).into();
+        }
+
+        self.albedo.value(0.0, 0.0, hit.point) * (cosine / std::f32::consts::PI)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        self.albedo.value(0.0, 0.0, hit.point)
+    }
 }
 
 pub struct Metal {
@@ -64,15 +120,191 @@ impl Material for Metal {
             None
         }
     }
+
+    fn albedo(&self, _hit: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+}
+
+// rough metal using a GGX microfacet distribution, eta and k are the real and
+// imaginary parts of the index of refraction per color channel
+pub struct Conductor {
+    eta: Vec3,
+    k: Vec3,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: f32) -> Self {
+        Self::anisotropic(eta, k, roughness, roughness)
+    }
+
+    // roughness_u runs along the surface tangent and roughness_v across it,
+    // surfaces without a tangent get an arbitrary orientation
+    pub fn anisotropic(eta: Vec3, k: Vec3, roughness_u: f32, roughness_v: f32) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(
+                TrowbridgeReitz::roughness_to_alpha(roughness_u),
+                TrowbridgeReitz::roughness_to_alpha(roughness_v),
+            ),
+        }
+    }
+
+    pub fn gold(roughness: f32) -> Self {
+        Self::new(
+            (0.143, 0.374, 1.442).into(),
+            (3.983, 2.385, 1.603).into(),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        Self::new(
+            (0.200, 0.924, 1.102).into(),
+            (3.912, 2.452, 2.142).into(),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f32) -> Self {
+        Self::new(
+            (1.657, 0.880, 0.521).into(),
+            (9.224, 6.270, 4.837).into(),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f32) -> Self {
+        Self::new(
+            (0.155, 0.117, 0.138).into(),
+            (4.828, 3.122, 2.147).into(),
+            roughness,
+        )
+    }
+
+    // shading frame aligned with the surface tangent so anisotropy follows
+    // the uv layout
+    fn frame(hit: &HitRecord) -> Onb {
+        Onb::from_w_and_tangent(&hit.normal, &hit.tangent)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        let frame = Self::frame(hit);
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.is_smooth() {
+            let wi = Vec3::new(-wo.x, -wo.y, wo.z);
+            let attenuation = fresnel_conductor(wo.z, &self.eta, &self.k);
+            return Some((Ray::new(hit.point, frame.to_world(&wi)), attenuation));
+        }
+
+        let mut rng = rand::thread_rng();
+        let wm = self
+            .distribution
+            .sample_visible_normal(&wo, rng.gen::<f32>(), rng.gen::<f32>());
+        let wi = reflect(&wo, &wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        // sampling visible normals leaves only fresnel and the shadowing term
+        let attenuation = fresnel_conductor(wo.dot(&wm), &self.eta, &self.k)
+            * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo));
+
+        Some((Ray::new(hit.point, frame.to_world(&wi)), attenuation))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        if self.distribution.is_smooth() {
+            return (0.0, 0.0, 0.0).into();
+        }
+
+        let frame = Self::frame(hit);
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        let wi = frame.to_local(direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return (0.0, 0.0, 0.0).into();
+        }
+
+        let wm = (wo + wi).make_unit_vector();
+        let f = fresnel_conductor(wo.dot(&wm), &self.eta, &self.k);
+
+        // cosine of wi cancels with the denominator of the brdf
+        f * (self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z))
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Vec3 {
+        fresnel_conductor(1.0, &self.eta, &self.k)
+    }
 }
 
 pub struct Dielectric {
-    ref_idx: f32,
+    ior: Ior,
+    // beer-lambert absorption coefficient per unit of distance inside
+    absorption: Vec3,
+    thin_film: Option<ThinFilm>,
+}
+
+// interference coating on the outside of a dielectric, thickness in nanometers
+#[derive(Clone, Copy, Debug)]
+struct ThinFilm {
+    thickness: f32,
+    ior: f32,
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f32) -> Self {
-        Self { ref_idx }
+        Self::with_absorption(ref_idx, (0.0, 0.0, 0.0).into())
+    }
+
+    pub fn with_absorption(ref_idx: f32, absorption: Vec3) -> Self {
+        Self::with_ior(Ior::Constant(ref_idx), absorption)
+    }
+
+    // colored glass described by the color white light takes on after
+    // traveling distance through it
+    pub fn tinted(ref_idx: f32, color: Vec3, distance: f32) -> Self {
+        let absorption = |transmittance: f32| -transmittance.max(1e-6).ln() / distance;
+        Self::with_absorption(
+            ref_idx,
+            Vec3::new(
+                absorption(color.x),
+                absorption(color.y),
+                absorption(color.z),
+            ),
+        )
+    }
+
+    // glass whose index varies with wavelength, splitting white light
+    pub fn dispersive(ior: Ior) -> Self {
+        Self::with_ior(ior, (0.0, 0.0, 0.0).into())
+    }
+
+    pub fn with_ior(ior: Ior, absorption: Vec3) -> Self {
+        Self {
+            ior,
+            absorption,
+            thin_film: None,
+        }
+    }
+
+    // coats the surface with a film, e.g. soap at a few hundred nanometers
+    // thick with an index of 1.33
+    pub fn with_thin_film(mut self, thickness: f32, ior: f32) -> Self {
+        self.thin_film = Some(ThinFilm { thickness, ior });
+        self
+    }
+
+    // whether paths through this material need to carry a single wavelength
+    fn is_spectral(&self) -> bool {
+        self.ior.is_dispersive() || self.thin_film.is_some()
     }
 }
 
@@ -91,40 +323,965 @@ pub fn slow_get_double() -> f32 {
 impl Material for Dielectric {
     fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
         let reflected = ray.direction.reflect(&hit.normal);
+        let inside = !hit.front_face;
+
+        // a ray hitting the boundary from inside has traveled through the
+        // medium since its last bounce
+        let mut attenuation = if inside {
+            let dist = hit.t * ray.direction.magnitude();
+            (-dist * self.absorption).exp()
+        } else {
+            Vec3::new(1.0, 1.0, 1.0)
+        };
+
+        // a white path hitting wavelength dependent glass is narrowed down to
+        // one wavelength, weighted by the color that wavelength contributes
+        let mut wavelength = ray.wavelength;
+        if wavelength.is_none() && self.is_spectral() {
+            let sampled = sample_wavelength(slow_get_double());
+            attenuation.comp_mul(&wavelength_to_rgb(sampled));
+            wavelength = Some(sampled);
+        }
+        let ref_idx = match wavelength {
+            Some(wavelength) => self.ior.at(wavelength),
+            None => self.ior.nominal(),
+        };
+
+        let incident_cosine = -ray.direction.dot(&hit.normal) / ray.direction.magnitude();
+        let (ni_over_nt, cosine) = if inside {
+            (ref_idx, ref_idx * incident_cosine)
+        } else {
+            (1.0 / ref_idx, incident_cosine)
+        };
+
+        let refracted = ray.direction.refract(&hit.normal, ni_over_nt);
+
+        let reflect_prob = match (refracted.is_some(), self.thin_film, wavelength) {
+            (false, _, _) => 1.0,
+            (true, Some(film), Some(wavelength)) => {
+                let (n1, n3) = if inside {
+                    (ref_idx, 1.0)
+                } else {
+                    (1.0, ref_idx)
+                };
+                thin_film_reflectance(
+                    incident_cosine,
+                    n1,
+                    film.ior,
+                    n3,
+                    film.thickness,
+                    wavelength,
+                )
+            }
+            (true, _, _) => schlick(cosine, ref_idx),
+        };
+
+        if slow_get_double() < reflect_prob {
+            Some((
+                Ray::with_wavelength(hit.point, reflected, wavelength),
+                attenuation,
+            ))
+        } else {
+            Some((
+                Ray::with_wavelength(hit.point, refracted.unwrap(), wavelength),
+                attenuation,
+            ))
+        }
+    }
+
+    fn is_wavelength_dependent(&self) -> bool {
+        self.is_spectral()
+    }
+}
+
+// translucent material for skin, wax or marble, a smooth dielectric
+// boundary around a scattering medium that paths random walk through
+pub struct Subsurface {
+    boundary: Dielectric,
+    medium: HomogeneousMedium,
+}
+
+impl Subsurface {
+    pub fn new(ref_idx: f32, medium: HomogeneousMedium) -> Self {
+        Self {
+            boundary: Dielectric::new(ref_idx),
+            medium,
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        self.boundary.scatter(ray, hit)
+    }
+
+    fn interior_medium(&self) -> Option<&HomogeneousMedium> {
+        Some(&self.medium)
+    }
+}
+
+// frosted glass, GGX reflection and transmission lobes over a dielectric
+// boundary, ref_idx is the index inside the surface relative to outside
+pub struct RoughDielectric {
+    ref_idx: f32,
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(ref_idx: f32, roughness: f32) -> Self {
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        Self {
+            ref_idx,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
         let attenuation = Vec3::new(1.0, 1.0, 1.0);
+        // frame keeps the outward normal, wo.z < 0 means we are inside
+        let frame = Onb::from_w(&hit.outward_normal());
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        if wo.z == 0.0 {
+            return None;
+        }
 
-        let (outward_normal, ni_over_nt, cosine) = if ray.direction.dot(&hit.normal) > 0.0 {
-            let cosine = self.ref_idx * ray.direction.dot(&hit.normal) / ray.direction.magnitude();
+        let mut rng = rand::thread_rng();
+        let wm = if self.distribution.is_smooth() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.distribution
+                .sample_visible_normal(&wo, rng.gen::<f32>(), rng.gen::<f32>())
+        };
 
-            (-1.0 * hit.normal, self.ref_idx, cosine)
+        let reflect_prob = fresnel_dielectric(wo.dot(&wm), self.ref_idx);
+        let wi = if rng.gen::<f32>() < reflect_prob {
+            let wi = reflect(&wo, &wm);
+            if wo.z * wi.z <= 0.0 {
+                return None;
+            }
+            wi
         } else {
-            let cosine = -1.0 * ray.direction.dot(&hit.normal) / ray.direction.magnitude();
-            (hit.normal, 1.0 / self.ref_idx, cosine)
+            let (wi, _) = refract(&wo, &wm, self.ref_idx)?;
+            if wo.z * wi.z >= 0.0 {
+                return None;
+            }
+            wi
         };
 
-        let refracted = ray.direction.refract(&outward_normal, ni_over_nt);
+        // fresnel cancels with the lobe choice and visible normal sampling
+        // leaves only the shadowing term
+        let attenuation = if self.distribution.is_smooth() {
+            attenuation
+        } else {
+            attenuation * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo))
+        };
+
+        Some((Ray::new(hit.point, frame.to_world(&wi)), attenuation))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        if self.distribution.is_smooth() {
+            return (0.0, 0.0, 0.0).into();
+        }
+
+        let frame = Onb::from_w(&hit.outward_normal());
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        let wi = frame.to_local(direction);
+        let (value, _) = self.distribution.dielectric_eval(&wo, &wi, self.ref_idx);
+
+        Vec3::new(value, value, value)
+    }
+}
+
+// Disney style uber material, every parameter is a texture so it can vary
+// over the surface, scalar parameters are read from the x channel
+pub struct Principled {
+    base_color: Box<dyn Texture + Send + Sync>,
+    metallic: Box<dyn Texture + Send + Sync>,
+    roughness: Box<dyn Texture + Send + Sync>,
+    specular: Box<dyn Texture + Send + Sync>,
+    specular_tint: Box<dyn Texture + Send + Sync>,
+    sheen: Box<dyn Texture + Send + Sync>,
+    clearcoat: Box<dyn Texture + Send + Sync>,
+    transmission: Box<dyn Texture + Send + Sync>,
+    emission: Box<dyn Texture + Send + Sync>,
+    ior: f32,
+}
+
+// principled parameters looked up at a single hit point
+struct PrincipledSample {
+    base_color: Vec3,
+    metallic: f32,
+    roughness: f32,
+    specular: f32,
+    specular_tint: f32,
+    sheen: f32,
+    clearcoat: f32,
+    transmission: f32,
+}
+
+const CLEARCOAT_ALPHA: f32 = 0.05;
+
+fn scalar_texture(value: f32) -> Box<dyn Texture + Send + Sync> {
+    Box::new(ConstantTexture::new((value, value, value).into()))
+}
+
+fn luminance(color: &Vec3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+// base color normalized to unit luminance
+fn hue(color: &Vec3) -> Vec3 {
+    if luminance(color) > 0.0 {
+        *color * (1.0 / luminance(color))
+    } else {
+        Vec3::new(1.0, 1.0, 1.0)
+    }
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+impl Principled {
+    pub fn new(base_color: Box<dyn Texture + Send + Sync>) -> Self {
+        Self {
+            base_color,
+            metallic: scalar_texture(0.0),
+            roughness: scalar_texture(0.5),
+            specular: scalar_texture(0.5),
+            specular_tint: scalar_texture(0.0),
+            sheen: scalar_texture(0.0),
+            clearcoat: scalar_texture(0.0),
+            transmission: scalar_texture(0.0),
+            emission: scalar_texture(0.0),
+            ior: 1.5,
+        }
+    }
+
+    pub fn with_metallic(mut self, metallic: Box<dyn Texture + Send + Sync>) -> Self {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: Box<dyn Texture + Send + Sync>) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_specular(mut self, specular: Box<dyn Texture + Send + Sync>) -> Self {
+        self.specular = specular;
+        self
+    }
+
+    pub fn with_specular_tint(mut self, specular_tint: Box<dyn Texture + Send + Sync>) -> Self {
+        self.specular_tint = specular_tint;
+        self
+    }
+
+    pub fn with_sheen(mut self, sheen: Box<dyn Texture + Send + Sync>) -> Self {
+        self.sheen = sheen;
+        self
+    }
+
+    pub fn with_clearcoat(mut self, clearcoat: Box<dyn Texture + Send + Sync>) -> Self {
+        self.clearcoat = clearcoat;
+        self
+    }
 
-        let reflect_prob = if refracted.is_some() {
-            schlick(cosine, self.ref_idx)
+    pub fn with_transmission(mut self, transmission: Box<dyn Texture + Send + Sync>) -> Self {
+        self.transmission = transmission;
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Box<dyn Texture + Send + Sync>) -> Self {
+        self.emission = emission;
+        self
+    }
+
+    pub fn with_ior(mut self, ior: f32) -> Self {
+        self.ior = ior;
+        self
+    }
+
+    fn lookup(&self, hit: &HitRecord) -> PrincipledSample {
+        let scalar =
+            |texture: &dyn Texture| texture.value(hit.u, hit.v, hit.point).x.clamp(0.0, 1.0);
+
+        PrincipledSample {
+            base_color: self.base_color.value(hit.u, hit.v, hit.point),
+            metallic: scalar(self.metallic.as_ref()),
+            roughness: scalar(self.roughness.as_ref()),
+            specular: scalar(self.specular.as_ref()),
+            specular_tint: scalar(self.specular_tint.as_ref()),
+            sheen: scalar(self.sheen.as_ref()),
+            clearcoat: scalar(self.clearcoat.as_ref()),
+            transmission: scalar(self.transmission.as_ref()),
+        }
+    }
+
+    // opaque surfaces shade whichever side was hit, transmissive ones keep
+    // the outward normal so wo.z < 0 means the ray is inside
+    fn frame(hit: &HitRecord, params: &PrincipledSample) -> Onb {
+        if params.transmission > 0.0 {
+            Onb::from_w(&hit.outward_normal())
         } else {
-            1.0
+            Onb::from_w(&hit.normal)
+        }
+    }
+
+    // selection probabilities for the diffuse, specular, clearcoat and
+    // transmission lobes, only transmission exists below the surface
+    fn lobe_probabilities(params: &PrincipledSample, wo: &Vec3) -> [f32; 4] {
+        if wo.z < 0.0 {
+            return [0.0, 0.0, 0.0, 1.0];
+        }
+        let dielectric = 1.0 - params.metallic;
+        let weights = [
+            dielectric * (1.0 - params.transmission),
+            1.0 - dielectric * params.transmission,
+            0.25 * params.clearcoat,
+            dielectric * params.transmission,
+        ];
+        let total: f32 = weights.iter().sum();
+
+        [
+            weights[0] / total,
+            weights[1] / total,
+            weights[2] / total,
+            weights[3] / total,
+        ]
+    }
+
+    // returns the bsdf times the cosine of wi together with the combined
+    // sampling density of every lobe
+    fn evaluate(&self, params: &PrincipledSample, wo: &Vec3, wi: &Vec3) -> (Vec3, f32) {
+        let probabilities = Self::lobe_probabilities(params, wo);
+        let alpha = TrowbridgeReitz::roughness_to_alpha(params.roughness).max(1e-3);
+        let specular = TrowbridgeReitz::new(alpha, alpha);
+        let clearcoat = TrowbridgeReitz::new(CLEARCOAT_ALPHA, CLEARCOAT_ALPHA);
+        let dielectric = 1.0 - params.metallic;
+
+        let mut value = Vec3::new(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+
+        if wo.z > 0.0 && wi.z > 0.0 {
+            let wm = (*wo + *wi).make_unit_vector();
+            let cos_d = wi.dot(&wm);
+
+            // burley diffuse with retro-reflection plus sheen at grazing angles
+            if probabilities[0] > 0.0 {
+                let fd90 = 0.5 + 2.0 * params.roughness * cos_d * cos_d;
+                let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+                    * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+                let diffuse = params.base_color * (fd / std::f32::consts::PI);
+
+                let tint = hue(&params.base_color);
+                let sheen_color = lerp(Vec3::new(1.0, 1.0, 1.0), tint, 0.5);
+                let sheen = sheen_color * (params.sheen * schlick_weight(cos_d));
+
+                value += (diffuse + sheen) * (dielectric * (1.0 - params.transmission) * wi.z);
+                pdf += probabilities[0] * wi.z / std::f32::consts::PI;
+            }
+
+            // metallic blends the specular color from a tinted dielectric
+            // reflectance to the base color
+            if probabilities[1] > 0.0 {
+                let tint = hue(&params.base_color);
+                let dielectric_f0 = lerp(Vec3::new(1.0, 1.0, 1.0), tint, params.specular_tint)
+                    * (0.08 * params.specular);
+                let f0 = lerp(dielectric_f0, params.base_color, params.metallic);
+                let f = lerp(f0, Vec3::new(1.0, 1.0, 1.0), schlick_weight(cos_d));
+
+                let d = specular.d(&wm);
+                let g = specular.g(wo, wi);
+                value += f * (d * g / (4.0 * wo.z) * (1.0 - dielectric * params.transmission));
+                pdf += probabilities[1] * specular.reflection_pdf(wo, wi);
+            }
+
+            if probabilities[2] > 0.0 {
+                let f = 0.04 + 0.96 * schlick_weight(cos_d);
+                let d = clearcoat.d(&wm);
+                let g = clearcoat.g(wo, wi);
+                let cc = 0.25 * params.clearcoat * f * d * g / (4.0 * wo.z);
+                value += Vec3::new(cc, cc, cc);
+                pdf += probabilities[2] * clearcoat.reflection_pdf(wo, wi);
+            }
+        }
+
+        if probabilities[3] > 0.0 {
+            let (f, lobe_pdf) = specular.dielectric_eval(wo, wi, self.ior);
+            // tint on the way in and out so a round trip picks up base color
+            let tint = if wo.z * wi.z < 0.0 {
+                params.base_color.gamma_two()
+            } else {
+                Vec3::new(1.0, 1.0, 1.0)
+            };
+            let weight = if wo.z < 0.0 {
+                1.0
+            } else {
+                dielectric * params.transmission
+            };
+            value += tint * (f * weight);
+            pdf += probabilities[3] * lobe_pdf;
+        }
+
+        (value, pdf)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        let params = self.lookup(hit);
+        let frame = Self::frame(hit, &params);
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        if wo.z == 0.0 {
+            return None;
+        }
+
+        let probabilities = Self::lobe_probabilities(&params, &wo);
+        let alpha = TrowbridgeReitz::roughness_to_alpha(params.roughness).max(1e-3);
+
+        let mut rng = rand::thread_rng();
+        let mut choice = rng.gen::<f32>();
+        let mut lobe = 0;
+        while lobe < 3 && choice >= probabilities[lobe] {
+            choice -= probabilities[lobe];
+            lobe += 1;
+        }
+
+        let wi = match lobe {
+            0 => random_cosine_direction(),
+            1 | 2 => {
+                let distribution = if lobe == 1 {
+                    TrowbridgeReitz::new(alpha, alpha)
+                } else {
+                    TrowbridgeReitz::new(CLEARCOAT_ALPHA, CLEARCOAT_ALPHA)
+                };
+                let wm =
+                    distribution.sample_visible_normal(&wo, rng.gen::<f32>(), rng.gen::<f32>());
+                reflect(&wo, &wm)
+            }
+            _ => {
+                let distribution = TrowbridgeReitz::new(alpha, alpha);
+                // wm is in the upper hemisphere, so from inside the object
+                // wo.dot(&wm) is negative and fresnel and refract invert eta
+                let wm =
+                    distribution.sample_visible_normal(&wo, rng.gen::<f32>(), rng.gen::<f32>());
+                if rng.gen::<f32>() < fresnel_dielectric(wo.dot(&wm), self.ior) {
+                    reflect(&wo, &wm)
+                } else {
+                    refract(&wo, &wm, self.ior)?.0
+                }
+            }
         };
 
-        if slow_get_double() < reflect_prob {
-            Some((Ray::new(hit.point, reflected), attenuation))
+        let (value, pdf) = self.evaluate(&params, &wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((
+            Ray::new(hit.point, frame.to_world(&wi)),
+            value * (1.0 / pdf),
+        ))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        let params = self.lookup(hit);
+        let frame = Self::frame(hit, &params);
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        let wi = frame.to_local(direction);
+
+        self.evaluate(&params, &wo, &wi).0
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        self.emission.value(hit.u, hit.v, hit.point)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        self.base_color.value(hit.u, hit.v, hit.point)
+    }
+}
+
+// shades base with a perturbed normal, rejecting directions where the
+// shading and geometric normals disagree on the side so light can't leak
+// through the surface
+fn scatter_with_normal(
+    base: &(dyn Material + Send + Sync),
+    ray: &Ray,
+    hit: &HitRecord,
+    normal: Vec3,
+) -> Option<(Ray, Vec3)> {
+    let geometric = hit.normal;
+    if ray.direction.dot(&geometric) * ray.direction.dot(&normal) <= 0.0 {
+        return base.scatter(ray, hit);
+    }
+
+    let mut shading = *hit;
+    shading.normal = normal;
+    let (scattered, attenuation) = base.scatter(ray, &shading)?;
+    if scattered.direction.dot(&geometric) * scattered.direction.dot(&normal) <= 0.0 {
+        return None;
+    }
+
+    Some((scattered, attenuation))
+}
+
+fn eval_with_normal(
+    base: &(dyn Material + Send + Sync),
+    ray: &Ray,
+    hit: &HitRecord,
+    direction: &Vec3,
+    normal: Vec3,
+) -> Vec3 {
+    let geometric = hit.normal;
+    if ray.direction.dot(&geometric) * ray.direction.dot(&normal) <= 0.0 {
+        return base.eval(ray, hit, direction);
+    }
+    if direction.dot(&geometric) * direction.dot(&normal) <= 0.0 {
+        return (0.0, 0.0, 0.0).into();
+    }
+
+    let mut shading = *hit;
+    shading.normal = normal;
+    base.eval(ray, &shading, direction)
+}
+
+// tangent space normal map, the texture stores the normal remapped from
+// [-1, 1] to [0, 1] with z pointing away from the surface
+pub struct NormalMap {
+    base: Box<dyn Material + Send + Sync>,
+    map: Box<dyn Texture + Send + Sync>,
+}
+
+impl NormalMap {
+    pub fn new(base: Box<dyn Material + Send + Sync>, map: Box<dyn Texture + Send + Sync>) -> Self {
+        Self { base, map }
+    }
+
+    fn normal(&self, hit: &HitRecord) -> Vec3 {
+        let frame = Onb::from_w_and_tangent(&hit.normal, &hit.tangent);
+        let texel = self.map.value(hit.u, hit.v, hit.point);
+        let local = 2.0 * texel - Vec3::new(1.0, 1.0, 1.0);
+        // basis from from_w_and_tangent is right handed with v along the
+        // bitangent
+        let normal = frame.to_world(&local);
+        if normal.squared_mag() == 0.0 {
+            return hit.normal;
+        }
+
+        normal.make_unit_vector()
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        scatter_with_normal(self.base.as_ref(), ray, hit, self.normal(hit))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        eval_with_normal(self.base.as_ref(), ray, hit, direction, self.normal(hit))
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        self.base.emitted(hit)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        hit: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.base.emitted_spectrum(hit, wavelengths)
+    }
+
+    fn is_wavelength_dependent(&self) -> bool {
+        self.base.is_wavelength_dependent()
+    }
+
+    fn interior_medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.interior_medium()
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        self.base.albedo(hit)
+    }
+
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        let mut shading = *hit;
+        shading.normal = self.normal(hit);
+        self.base.shading_normal(&shading)
+    }
+}
+
+// bump map driven by the x channel of any texture, the normal is rebuilt from
+// finite differences of the height along the surface tangents
+pub struct BumpMap {
+    base: Box<dyn Material + Send + Sync>,
+    height: Box<dyn Texture + Send + Sync>,
+    scale: f32,
+}
+
+impl BumpMap {
+    pub fn new(
+        base: Box<dyn Material + Send + Sync>,
+        height: Box<dyn Texture + Send + Sync>,
+        scale: f32,
+    ) -> Self {
+        Self {
+            base,
+            height,
+            scale,
+        }
+    }
+
+    fn normal(&self, hit: &HitRecord) -> Vec3 {
+        let (tangent, bitangent) = if hit.tangent.squared_mag() == 0.0 {
+            let frame = Onb::from_w(&hit.normal);
+            (frame.u, frame.v)
+        } else {
+            (hit.tangent, hit.bitangent)
+        };
+
+        let delta = 0.0005;
+        let height = |u: f32, v: f32, point: Vec3| self.scale * self.height.value(u, v, point).x;
+        let displacement = height(hit.u, hit.v, hit.point);
+        let u_displaced = height(hit.u + delta, hit.v, hit.point + delta * tangent);
+        let v_displaced = height(hit.u, hit.v + delta, hit.point + delta * bitangent);
+
+        let dpdu = tangent + ((u_displaced - displacement) / delta) * hit.normal;
+        let dpdv = bitangent + ((v_displaced - displacement) / delta) * hit.normal;
+        let normal = dpdu.cross(&dpdv);
+        if normal.squared_mag() == 0.0 {
+            return hit.normal;
+        }
+        let normal = normal.make_unit_vector();
+
+        // keep the perturbed normal on the same side as the geometric one
+        if normal.dot(&hit.normal) < 0.0 {
+            -normal
+        } else {
+            normal
+        }
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        scatter_with_normal(self.base.as_ref(), ray, hit, self.normal(hit))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        eval_with_normal(self.base.as_ref(), ray, hit, direction, self.normal(hit))
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        self.base.emitted(hit)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        hit: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.base.emitted_spectrum(hit, wavelengths)
+    }
+
+    fn is_wavelength_dependent(&self) -> bool {
+        self.base.is_wavelength_dependent()
+    }
+
+    fn interior_medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.interior_medium()
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        self.base.albedo(hit)
+    }
+
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        let mut shading = *hit;
+        shading.normal = self.normal(hit);
+        self.base.shading_normal(&shading)
+    }
+}
+
+// picks between two materials per hit, the x channel of weight is the chance
+// of using second, e.g. rust or dirt masks over a metal
+pub struct MixMaterial {
+    first: Box<dyn Material + Send + Sync>,
+    second: Box<dyn Material + Send + Sync>,
+    weight: Box<dyn Texture + Send + Sync>,
+}
+
+impl MixMaterial {
+    pub fn new(
+        first: Box<dyn Material + Send + Sync>,
+        second: Box<dyn Material + Send + Sync>,
+        weight: Box<dyn Texture + Send + Sync>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            weight,
+        }
+    }
+
+    fn weight(&self, u: f32, v: f32, point: Vec3) -> f32 {
+        self.weight.value(u, v, point).x.clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        if slow_get_double() < self.weight(hit.u, hit.v, hit.point) {
+            self.second.scatter(ray, hit)
+        } else {
+            self.first.scatter(ray, hit)
+        }
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        let weight = self.weight(hit.u, hit.v, hit.point);
+        (1.0 - weight) * self.first.eval(ray, hit, direction)
+            + weight * self.second.eval(ray, hit, direction)
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        let weight = self.weight(hit.u, hit.v, hit.point);
+        (1.0 - weight) * self.first.emitted(hit) + weight * self.second.emitted(hit)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        hit: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let weight = self.weight(hit.u, hit.v, hit.point);
+        self.first.emitted_spectrum(hit, wavelengths) * (1.0 - weight)
+            + self.second.emitted_spectrum(hit, wavelengths) * weight
+    }
+
+    fn is_wavelength_dependent(&self) -> bool {
+        self.first.is_wavelength_dependent() || self.second.is_wavelength_dependent()
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        let weight = self.weight(hit.u, hit.v, hit.point);
+        (1.0 - weight) * self.first.albedo(hit) + weight * self.second.albedo(hit)
+    }
+
+    // there is no hit to look the weight up at, so the first input with a
+    // medium wins
+    fn interior_medium(&self) -> Option<&HomogeneousMedium> {
+        self.first
+            .interior_medium()
+            .or_else(|| self.second.interior_medium())
+    }
+
+    // the input with the larger weight at the hit
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        if self.weight(hit.u, hit.v, hit.point) < 0.5 {
+            self.first.shading_normal(hit)
         } else {
-            Some((Ray::new(hit.point, refracted.unwrap()), attenuation))
+            self.second.shading_normal(hit)
+        }
+    }
+}
+
+// dielectric clearcoat layered over any base material, light either reflects
+// off the coat or passes through it twice, picking up tint each way, to
+// scatter off the base. refraction through the thin coat is ignored
+pub struct Coated {
+    base: Box<dyn Material + Send + Sync>,
+    ior: f32,
+    distribution: TrowbridgeReitz,
+    tint: Vec3,
+}
+
+impl Coated {
+    pub fn new(
+        base: Box<dyn Material + Send + Sync>,
+        ior: f32,
+        roughness: f32,
+        tint: Vec3,
+    ) -> Self {
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        Self {
+            base,
+            ior,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            tint,
+        }
+    }
+
+    fn frame(hit: &HitRecord) -> Onb {
+        Onb::from_w(&hit.normal)
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        let frame = Self::frame(hit);
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+        let wm = if self.distribution.is_smooth() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.distribution
+                .sample_visible_normal(&wo, rng.gen::<f32>(), rng.gen::<f32>())
+        };
+
+        // fresnel cancels with the choice of reflecting off the coat
+        if rng.gen::<f32>() < fresnel_dielectric(wo.dot(&wm), self.ior) {
+            let wi = reflect(&wo, &wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            let attenuation = if self.distribution.is_smooth() {
+                Vec3::new(1.0, 1.0, 1.0)
+            } else {
+                let g = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+                Vec3::new(g, g, g)
+            };
+            return Some((Ray::new(hit.point, frame.to_world(&wi)), attenuation));
+        }
+
+        let (scattered, attenuation) = self.base.scatter(ray, hit)?;
+        let wi = frame.to_local(&scattered.direction.make_unit_vector());
+        if wi.z <= 0.0 {
+            // transmitted through the base, the coat still tints it once
+            return Some((scattered, attenuation.make_comp_mul(&self.tint)));
+        }
+        let exit = 1.0 - fresnel_dielectric(wi.z, self.ior);
+
+        Some((
+            scattered,
+            attenuation
+                .make_comp_mul(&self.tint)
+                .make_comp_mul(&self.tint)
+                * exit,
+        ))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        let frame = Self::frame(hit);
+        let wo = frame.to_local(&-ray.direction.make_unit_vector());
+        let wi = frame.to_local(direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return (0.0, 0.0, 0.0).into();
+        }
+
+        let transmitted =
+            (1.0 - fresnel_dielectric(wo.z, self.ior)) * (1.0 - fresnel_dielectric(wi.z, self.ior));
+        let base = self
+            .base
+            .eval(ray, hit, direction)
+            .make_comp_mul(&self.tint)
+            .make_comp_mul(&self.tint)
+            * transmitted;
+
+        if self.distribution.is_smooth() {
+            return base;
         }
+        let wm = (wo + wi).make_unit_vector();
+        let coat = self.distribution.d(&wm)
+            * self.distribution.g(&wo, &wi)
+            * fresnel_dielectric(wo.dot(&wm), self.ior)
+            / (4.0 * wo.z);
+
+        base + Vec3::new(coat, coat, coat)
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        self.base.emitted(hit).make_comp_mul(&self.tint)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        hit: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.base.emitted_spectrum(hit, wavelengths)
+            * SampledSpectrum::from_rgb_reflectance(&self.tint, wavelengths)
+    }
+
+    fn is_wavelength_dependent(&self) -> bool {
+        self.base.is_wavelength_dependent()
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        self.base.albedo(hit).make_comp_mul(&self.tint)
     }
+
+    fn interior_medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.interior_medium()
+    }
+
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        self.base.shading_normal(hit)
+    }
+}
+
+// which faces of a surface emit or shade, the front is the side the outward
+// normal points to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sidedness {
+    OneSided,
+    TwoSided,
 }
 
 pub struct DiffuseLight {
     emit: Box<dyn Texture + Send + Sync>,
+    sidedness: Sidedness,
+    // illuminant and its scale when emitting a measured spectrum, emit then
+    // holds the matching rgb color
+    spectrum: Option<(Illuminant, f32)>,
 }
 
 impl DiffuseLight {
     pub fn new(emit: Box<dyn Texture + Send + Sync>) -> Self {
-        Self { emit }
+        Self::with_sidedness(emit, Sidedness::TwoSided)
+    }
+
+    // only emits from the front face, like a panel light
+    pub fn one_sided(emit: Box<dyn Texture + Send + Sync>) -> Self {
+        Self::with_sidedness(emit, Sidedness::OneSided)
+    }
+
+    pub fn with_sidedness(emit: Box<dyn Texture + Send + Sync>, sidedness: Sidedness) -> Self {
+        Self {
+            emit,
+            sidedness,
+            spectrum: None,
+        }
+    }
+
+    // emits an illuminant spectrum scaled to the given luminance
+    pub fn spectral(illuminant: Illuminant, luminance: f32) -> Self {
+        let scale = luminance / illuminant.luminance();
+        Self {
+            emit: Box::new(ConstantTexture::new(illuminant.to_rgb() * scale)),
+            sidedness: Sidedness::TwoSided,
+            spectrum: Some((illuminant, scale)),
+        }
+    }
+
+    // black body at temperature in kelvin, e.g. 2700 for a warm bulb
+    pub fn blackbody(temperature: f32, luminance: f32) -> Self {
+        Self::spectral(Illuminant::Blackbody(temperature), luminance)
     }
 }
 
@@ -132,7 +1289,157 @@ impl Material for DiffuseLight {
     fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<(Ray, Vec3)> {
         None
     }
-    fn emitted(&self, u: f32, v: f32, point: Vec3) -> Vec3 {
-        self.emit.value(u, v, point)
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        if self.sidedness == Sidedness::OneSided && !hit.front_face {
+            return (0.0, 0.0, 0.0).into();
+        }
+        self.emit.value(hit.u, hit.v, hit.point)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        hit: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        if self.sidedness == Sidedness::OneSided && !hit.front_face {
+            return SampledSpectrum::new(0.0);
+        }
+        match self.spectrum {
+            Some((illuminant, scale)) => SampledSpectrum::from_fn(wavelengths, |wavelength| {
+                illuminant.at(wavelength) * scale
+            }),
+            None => SampledSpectrum::from_rgb_illuminant(&self.emitted(hit), wavelengths),
+        }
+    }
+}
+
+// restricts any material to its front face, back faces absorb everything and
+// emit nothing
+pub struct OneSided {
+    base: Box<dyn Material + Send + Sync>,
+}
+
+impl OneSided {
+    pub fn new(base: Box<dyn Material + Send + Sync>) -> Self {
+        Self { base }
+    }
+}
+
+impl Material for OneSided {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        if !hit.front_face {
+            return None;
+        }
+        self.base.scatter(ray, hit)
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        if !hit.front_face {
+            return (0.0, 0.0, 0.0).into();
+        }
+        self.base.eval(ray, hit, direction)
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        if !hit.front_face {
+            return (0.0, 0.0, 0.0).into();
+        }
+        self.base.emitted(hit)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        hit: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        if !hit.front_face {
+            return SampledSpectrum::new(0.0);
+        }
+        self.base.emitted_spectrum(hit, wavelengths)
+    }
+
+    fn is_wavelength_dependent(&self) -> bool {
+        self.base.is_wavelength_dependent()
+    }
+
+    fn interior_medium(&self) -> Option<&HomogeneousMedium> {
+        self.base.interior_medium()
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        self.base.albedo(hit)
+    }
+
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        self.base.shading_normal(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{Hittable, Sphere};
+
+    // ray from inside a unit sphere reaching its top at theta degrees from
+    // the normal
+    fn from_inside(theta: f32) -> (Sphere, Ray) {
+        let sphere = Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Box::new(RoughDielectric::new(1.5, 0.3)),
+        );
+        let theta = theta.to_radians();
+        let direction = Vec3::new(theta.sin(), 0.0, theta.cos());
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0) - 0.5 * direction, direction);
+
+        (sphere, ray)
+    }
+
+    // what scatter samples from inside the glass has to agree with what the
+    // bsdf and its pdf give for the same direction
+    #[test]
+    fn rough_dielectric_scatter_matches_eval_from_inside() {
+        let material = RoughDielectric::new(1.5, 0.3);
+        for &theta in [20.0, 60.0].iter() {
+            let (sphere, ray) = from_inside(theta);
+            let hit = sphere.hit(&ray, 0.001, f32::MAX).unwrap();
+            assert!(!hit.front_face);
+            let frame = Onb::from_w(&hit.outward_normal());
+            let wo = frame.to_local(&-ray.direction.make_unit_vector());
+
+            for _ in 0..10_000 {
+                let (scattered, attenuation) = match material.scatter(&ray, &hit) {
+                    Some(scattered) => scattered,
+                    None => continue,
+                };
+                let wi = frame.to_local(&scattered.direction.make_unit_vector());
+                let (value, pdf) = material.distribution.dielectric_eval(&wo, &wi, 1.5);
+                assert!(pdf > 0.0, "sampled a direction eval gives no density");
+                let weight = value / pdf;
+                assert!(
+                    (weight - attenuation.x).abs() <= 1e-3 * weight.max(1.0),
+                    "scatter weight {} but eval over pdf {}",
+                    attenuation.x,
+                    weight
+                );
+            }
+        }
+    }
+
+    // past the critical angle smooth and rough glass both reflect everything
+    #[test]
+    fn rough_dielectric_total_internal_reflection() {
+        let (sphere, ray) = from_inside(60.0);
+        let hit = sphere.hit(&ray, 0.001, f32::MAX).unwrap();
+        let mut reflected = 0;
+        for _ in 0..10_000 {
+            if let Some((scattered, _)) = hit.material.scatter(&ray, &hit) {
+                if scattered.direction.dot(&hit.outward_normal()) < 0.0 {
+                    reflected += 1;
+                }
+            }
+        }
+
+        assert!(reflected > 9_000, "only {} of 10000 reflected", reflected);
     }
 }
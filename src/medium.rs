@@ -0,0 +1,128 @@
+use crate::onb::Onb;
+use crate::vec::Vec3;
+
+use rand::Rng;
+use std::f32::consts::PI;
+
+// henyey-greenstein phase function, g in (-1, 1) goes from back scattering
+// through isotropic at 0 to forward scattering
+#[derive(Clone, Copy, Debug)]
+pub struct HenyeyGreenstein {
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f32) -> Self {
+        Self {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    // density for turning by an angle with cos_theta measured between the
+    // direction of travel before and after scattering
+    pub fn eval(&self, cos_theta: f32) -> f32 {
+        let denom = 1.0 + self.g * self.g - 2.0 * self.g * cos_theta;
+        (1.0 - self.g * self.g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    // samples a new direction of travel for a path traveling along direction,
+    // the pdf is eval of the angle between them
+    pub fn sample(&self, direction: &Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let u1 = rng.gen::<f32>();
+        let u2 = rng.gen::<f32>();
+
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let sq = (1.0 - self.g * self.g) / (1.0 + self.g - 2.0 * self.g * u1);
+            (1.0 + self.g * self.g - sq * sq) / (2.0 * self.g)
+        };
+        let cos_theta = cos_theta.clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let frame = Onb::from_w(direction);
+        frame.to_world(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+// what happened to a path crossing a stretch of medium, weight is the
+// throughput to apply for the event
+pub enum MediumEvent {
+    Scatter { distance: f32, weight: Vec3 },
+    Pass { weight: Vec3 },
+}
+
+// medium with constant absorption and scattering coefficients per unit of
+// distance
+#[derive(Clone, Copy, Debug)]
+pub struct HomogeneousMedium {
+    sigma_a: Vec3,
+    sigma_s: Vec3,
+    phase: HenyeyGreenstein,
+}
+
+impl HomogeneousMedium {
+    pub fn new(sigma_a: Vec3, sigma_s: Vec3, g: f32) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            phase: HenyeyGreenstein::new(g),
+        }
+    }
+
+    // single scattering albedo and the average distance between collisions
+    // are easier to pick than raw coefficients
+    pub fn from_albedo(albedo: Vec3, mean_free_path: f32, g: f32) -> Self {
+        let sigma_t = 1.0 / mean_free_path;
+        Self::new(
+            (Vec3::new(1.0, 1.0, 1.0) - albedo) * sigma_t,
+            albedo * sigma_t,
+            g,
+        )
+    }
+
+    pub fn phase(&self) -> &HenyeyGreenstein {
+        &self.phase
+    }
+
+    // samples a collision along a segment of length max_distance, colored
+    // media pick a channel to sample with and weight by the average pdf
+    pub fn sample(&self, max_distance: f32) -> MediumEvent {
+        let sigma_t = self.sigma_a + self.sigma_s;
+        let channels = [sigma_t.x, sigma_t.y, sigma_t.z];
+
+        let mut rng = rand::thread_rng();
+        let channel = channels[rng.gen_range(0, 3)];
+        let distance = if channel > 0.0 {
+            -(1.0 - rng.gen::<f32>()).ln() / channel
+        } else {
+            f32::MAX
+        };
+
+        let transmittance = |distance: f32| (-distance * sigma_t).exp();
+        if distance < max_distance {
+            let tr = transmittance(distance);
+            let pdf = (sigma_t.make_comp_mul(&tr)).dot(&Vec3::new(1.0, 1.0, 1.0)) / 3.0;
+            MediumEvent::Scatter {
+                distance,
+                weight: self.sigma_s.make_comp_mul(&tr) * (1.0 / pdf),
+            }
+        } else {
+            let tr = transmittance(max_distance);
+            let pdf = tr.dot(&Vec3::new(1.0, 1.0, 1.0)) / 3.0;
+            MediumEvent::Pass {
+                weight: if pdf > 0.0 {
+                    tr * (1.0 / pdf)
+                } else {
+                    Vec3::new(0.0, 0.0, 0.0)
+                },
+            }
+        }
+    }
+}
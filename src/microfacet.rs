@@ -0,0 +1,255 @@
+use crate::vec::Vec3;
+use std::f32::consts::PI;
+
+// GGX / Trowbridge-Reitz microfacet distribution, every direction here is in
+// the local shading frame where z is the surface normal
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f32, alpha_y: f32) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    // perceptually linear roughness in [0, 1] to distribution alpha
+    pub fn roughness_to_alpha(roughness: f32) -> f32 {
+        let roughness = roughness.clamp(0.0, 1.0);
+        roughness * roughness
+    }
+
+    // below this the lobe is narrower than anything we can sample, treat the
+    // surface as a perfect mirror instead
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f32 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let x = wm.x / self.alpha_x;
+        let y = wm.y / self.alpha_y;
+        let e = x * x + y * y + wm.z * wm.z;
+
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    pub fn lambda(&self, w: &Vec3) -> f32 {
+        if w.z == 0.0 {
+            return f32::MAX;
+        }
+        let ax = self.alpha_x * w.x;
+        let ay = self.alpha_y * w.y;
+        let tan2_alpha2 = (ax * ax + ay * ay) / (w.z * w.z);
+
+        0.5 * (-1.0 + (1.0 + tan2_alpha2).sqrt())
+    }
+
+    // smith masking for a single direction, symmetric in w.z so directions
+    // below the surface are handled too
+    pub fn g1(&self, w: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // height correlated smith masking-shadowing
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // samples a microfacet normal from the distribution of normals visible
+    // from wo, Heitz 2018 "Sampling the GGX Distribution of Visible Normals".
+    // the normal is always in the upper hemisphere, so for wo below the
+    // surface wo.dot(wm) is negative and fresnel and refraction see that wo
+    // is inside
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: f32, u2: f32) -> Vec3 {
+        // the distribution is symmetric, sample as if viewed from above
+        let wo = if wo.z < 0.0 { -*wo } else { *wo };
+
+        // stretch the view so the distribution becomes a hemisphere
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).make_unit_vector();
+
+        let lensq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) * (1.0 / lensq.sqrt())
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+
+        // sample the projected area of the visible hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let p2 = r * phi.sin();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * p2;
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // unstretch back to the ellipsoid
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).make_unit_vector()
+    }
+}
+
+impl TrowbridgeReitz {
+    // density of sample_visible_normal returning wm, an upper hemisphere
+    // normal that w sees from the side it is on
+    pub fn visible_normal_pdf(&self, w: &Vec3, wm: &Vec3) -> f32 {
+        if w.z == 0.0 || w.dot(wm) * w.z <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(w) * self.d(wm) * w.dot(wm).abs() / w.z.abs()
+    }
+
+    // density of wi when a visible normal of wo is sampled and wo reflected
+    // about it
+    pub fn reflection_pdf(&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        if wo.z * wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = (*wo + *wi).make_unit_vector();
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+
+        self.visible_normal_pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+
+    // bsdf times the cosine of wi and the sampling density of wi for a rough
+    // dielectric boundary, eta is the index inside over outside
+    pub fn dielectric_eval(&self, wo: &Vec3, wi: &Vec3, eta: f32) -> (f32, f32) {
+        let cos_theta_o = wo.z;
+        let cos_theta_i = wi.z;
+        if cos_theta_o == 0.0 || cos_theta_i == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        // generalized half vector, for refraction it depends on which side
+        // of the boundary wo is on
+        let is_reflection = cos_theta_o * cos_theta_i > 0.0;
+        let etap = if is_reflection {
+            1.0
+        } else if cos_theta_o > 0.0 {
+            eta
+        } else {
+            1.0 / eta
+        };
+        let wm = *wi * etap + *wo;
+        if wm.squared_mag() == 0.0 {
+            return (0.0, 0.0);
+        }
+        let wm = wm.make_unit_vector();
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+
+        // discard microfacets facing away from either direction
+        if wm.dot(wi) * cos_theta_i < 0.0 || wm.dot(wo) * cos_theta_o < 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let d = self.d(&wm);
+        let g = self.g(wo, wi);
+        let f = fresnel_dielectric(wo.dot(&wm), eta);
+        let visible_pdf = self.visible_normal_pdf(wo, &wm);
+
+        if is_reflection {
+            (
+                d * g * f / (4.0 * cos_theta_o.abs()),
+                visible_pdf / (4.0 * wo.dot(&wm).abs()) * f,
+            )
+        } else {
+            let denom = wi.dot(&wm) + wo.dot(&wm) / etap;
+            let denom = denom * denom;
+            (
+                d * (1.0 - f) * g * (wi.dot(&wm) * wo.dot(&wm) / (denom * cos_theta_o)).abs(),
+                visible_pdf * wi.dot(&wm).abs() / denom * (1.0 - f),
+            )
+        }
+    }
+}
+
+// unpolarized fresnel reflectance of a conductor with complex index of
+// refraction eta + ik, evaluated independently per color channel
+pub fn fresnel_conductor(cos_theta_i: f32, eta: &Vec3, k: &Vec3) -> Vec3 {
+    let channel = |eta: f32, k: f32| {
+        let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+        let cos2 = cos_theta_i * cos_theta_i;
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2 = k * k;
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_theta_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    };
+
+    Vec3::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+#[inline]
+pub fn reflect(wo: &Vec3, n: &Vec3) -> Vec3 {
+    -*wo + 2.0 * wo.dot(n) * *n
+}
+
+// unpolarized fresnel reflectance at a dielectric boundary, eta is the ratio
+// of the index inside over outside and a negative cosine means wo is inside
+pub fn fresnel_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    0.5 * (r_parl * r_parl + r_perp * r_perp)
+}
+
+// refracts wo, pointing away from the surface, through the plane with normal
+// n, returns the transmitted direction and the relative index it crossed
+pub fn refract(wo: &Vec3, n: &Vec3, eta: f32) -> Option<(Vec3, f32)> {
+    let mut cos_theta_i = n.dot(wo);
+    let mut eta = eta;
+    let mut n = *n;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+        n = -n;
+    }
+
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    Some((
+        -*wo * (1.0 / eta) + (cos_theta_i / eta - cos_theta_t) * n,
+        eta,
+    ))
+}
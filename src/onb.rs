@@ -0,0 +1,50 @@
+use crate::vec::Vec3;
+
+// orthonormal basis, w is the surface normal and u, v span the tangent plane
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = n.make_unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).make_unit_vector();
+        let u = w.cross(&v);
+
+        Self { u, v, w }
+    }
+
+    // basis with u aligned to the projection of tangent onto the plane of n,
+    // falls back to an arbitrary tangent when the projection is degenerate
+    pub fn from_w_and_tangent(n: &Vec3, tangent: &Vec3) -> Self {
+        let w = n.make_unit_vector();
+        let u = *tangent - tangent.dot(&w) * w;
+        if u.squared_mag() < 1e-12 {
+            return Self::from_w(n);
+        }
+        let u = u.make_unit_vector();
+        let v = w.cross(&u);
+
+        Self { u, v, w }
+    }
+
+    // takes a vector in world space into the basis
+    #[inline]
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+
+    // takes a vector in the basis back out to world space
+    #[inline]
+    pub fn to_world(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
@@ -3,11 +3,26 @@ use crate::vec::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    // set once a path has been narrowed down to a single wavelength in
+    // nanometers, e.g. after being split by a dispersive material
+    pub wavelength: Option<f32>,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(origin: Vec3, direction: Vec3, wavelength: Option<f32>) -> Self {
+        Self {
+            origin,
+            direction,
+            wavelength,
+        }
     }
 
     pub fn point_at_parameter(&self, t: f32) -> Vec3 {
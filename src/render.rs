@@ -0,0 +1,557 @@
+use crate::aov::Aovs;
+use crate::camera::Camera;
+use crate::hittable::Hittable;
+use crate::integrator::Integrator;
+use crate::light::LightList;
+use crate::stats::{self, Stats};
+use crate::vec::Vec3;
+
+use rand::Rng;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct RenderSettings {
+    pub cols: u32,
+    pub rows: u32,
+    // samples per pixel to stop at
+    pub samples: u32,
+    // samples every pixel gets before the framebuffer is handed back, more
+    // means fewer snapshots and less overhead
+    pub samples_per_pass: u32,
+    // stops after the first pass to end past this
+    pub time_limit: Option<Duration>,
+    pub threads: u32,
+    pub has_light: bool,
+    pub integrator: Integrator,
+    // also accumulate the first hit aovs
+    pub aovs: bool,
+    // how often progress is reported while a pass runs
+    pub progress_interval: Duration,
+}
+
+impl RenderSettings {
+    pub fn new(cols: u32, rows: u32, samples: u32) -> Self {
+        Self {
+            cols,
+            rows,
+            samples,
+            samples_per_pass: 16,
+            time_limit: None,
+            threads: 16,
+            has_light: false,
+            integrator: Integrator::PathTracer,
+            aovs: false,
+            progress_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+// how far along a render is
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    // samples taken over all pixels, including any from a resumed checkpoint
+    pub samples_done: u64,
+    pub samples_total: u64,
+    // camera samples taken by this call to render
+    pub samples: u64,
+    // every ray cast by this call to render, camera, bounce, shadow and aov
+    pub rays: u64,
+    pub elapsed: Duration,
+    pub time_limit: Option<Duration>,
+}
+
+impl Progress {
+    // in [0, 1], whichever of the sample budget and time limit is closer
+    pub fn fraction(&self) -> f32 {
+        let samples = self.samples_done as f64 / self.samples_total.max(1) as f64;
+        let time = match self.time_limit {
+            Some(limit) => self.elapsed.as_secs_f64() / limit.as_secs_f64().max(1e-9),
+            None => 0.0,
+        };
+
+        samples.max(time).min(1.0) as f32
+    }
+
+    pub fn samples_per_second(&self) -> f64 {
+        self.samples as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+
+    // time left at the rate so far, None until there is a rate to go on
+    pub fn eta(&self) -> Option<Duration> {
+        if self.samples == 0 {
+            return None;
+        }
+        let remaining = self.samples_total.saturating_sub(self.samples_done) as f64;
+        let mut eta = Duration::from_secs_f64(remaining / self.samples_per_second());
+        if let Some(limit) = self.time_limit {
+            eta = eta.min(limit.saturating_sub(self.elapsed));
+        }
+
+        Some(eta)
+    }
+}
+
+// fnv-1a hash of whatever decides what a render converges to, kept in
+// checkpoints so a render only resumes from one of the same image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Fingerprint(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn with_bytes(mut self, bytes: &[u8]) -> Self {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        self
+    }
+
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderSettings {
+    // the settings that change the converged image, not how it gets there.
+    // the sample target is left out so a render can resume to a higher one
+    pub fn fingerprint(&self) -> Fingerprint {
+        let integrator = format!("{:?}", self.integrator);
+        Fingerprint::new()
+            .with_bytes(&self.cols.to_le_bytes())
+            .with_bytes(&self.rows.to_le_bytes())
+            .with_bytes(&[self.has_light as u8, self.aovs as u8])
+            .with_bytes(integrator.as_bytes())
+    }
+}
+
+// running sums of every sample taken so far, pixels row by row from the top.
+// every pixel has the same number of samples
+pub struct Framebuffer {
+    pub cols: u32,
+    pub rows: u32,
+    color: Vec<Vec3>,
+    // empty unless the render accumulates aovs
+    aovs: Vec<Aovs>,
+    samples: u32,
+}
+
+impl Framebuffer {
+    pub fn new(cols: u32, rows: u32) -> Self {
+        let pixels = (cols * rows) as usize;
+        Self {
+            cols,
+            rows,
+            color: vec![Vec3::new(0.0, 0.0, 0.0); pixels],
+            aovs: vec![],
+            samples: 0,
+        }
+    }
+
+    // samples per pixel accumulated so far
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    // average linear radiance of every pixel
+    pub fn color(&self) -> Vec<Vec3> {
+        let scale = 1.0 / self.samples.max(1) as f32;
+        self.color.iter().map(|c| *c * scale).collect()
+    }
+
+    // empty unless the render accumulated aovs
+    pub fn aovs(&self) -> Vec<Aovs> {
+        let scale = 1.0 / self.samples.max(1) as f32;
+        self.aovs.iter().map(|aov| *aov * scale).collect()
+    }
+
+    // writes the sums and sample count so a render can pick up where it left
+    // off, along with the fingerprint of the render they belong to. only one
+    // sample count is kept since every pixel has the same, and no sampler
+    // state since samples draw fresh random numbers. goes through a
+    // temporary file so a crash mid write keeps the previous checkpoint
+    pub fn save_checkpoint<P: AsRef<Path>>(
+        &self,
+        path: P,
+        fingerprint: Fingerprint,
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        let layers = if self.aovs.is_empty() { 1 } else { 8 };
+        let mut bytes =
+            Vec::with_capacity(CHECKPOINT_MAGIC.len() + 24 + self.color.len() * layers * 12);
+        bytes.extend_from_slice(CHECKPOINT_MAGIC);
+        bytes.extend_from_slice(&fingerprint.value().to_le_bytes());
+        let has_aovs = !self.aovs.is_empty() as u32;
+        for value in [self.cols, self.rows, self.samples, has_aovs].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut push = |v: &Vec3| {
+            for value in [v.x, v.y, v.z].iter() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        for (pixel, color) in self.color.iter().enumerate() {
+            push(color);
+            if let Some(aovs) = self.aovs.get(pixel) {
+                for layer in aovs.layers().iter() {
+                    push(layer);
+                }
+            }
+        }
+
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, bytes)?;
+        fs::rename(&temporary, path)
+    }
+
+    // every sample is independent of the ones before it, so rendering on from
+    // a checkpoint with fresh random numbers converges to the same image as
+    // an uninterrupted render. fails with InvalidData if the checkpoint is
+    // from a render with a different fingerprint
+    pub fn load_checkpoint<P: AsRef<Path>>(path: P, fingerprint: Fingerprint) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if !bytes.starts_with(CHECKPOINT_MAGIC) {
+            return Err(invalid("not a render checkpoint"));
+        }
+        let bytes = &bytes[CHECKPOINT_MAGIC.len()..];
+        if bytes.len() < 24 {
+            return Err(invalid("truncated render checkpoint"));
+        }
+        let mut saved = [0; 8];
+        saved.copy_from_slice(&bytes[..8]);
+        if u64::from_le_bytes(saved) != fingerprint.value() {
+            return Err(invalid("checkpoint is from a different render"));
+        }
+
+        let mut values = bytes[8..].chunks_exact(4);
+        let mut next = || {
+            values
+                .next()
+                .map(|b| [b[0], b[1], b[2], b[3]])
+                .ok_or_else(|| invalid("truncated render checkpoint"))
+        };
+        let cols = u32::from_le_bytes(next()?);
+        let rows = u32::from_le_bytes(next()?);
+        let samples = u32::from_le_bytes(next()?);
+        let has_aovs = u32::from_le_bytes(next()?) != 0;
+
+        // check the size before trusting the header with an allocation
+        let layers = if has_aovs { 8 } else { 1 };
+        let expected = (cols as usize)
+            .checked_mul(rows as usize)
+            .and_then(|pixels| pixels.checked_mul(layers * 12))
+            .and_then(|body| body.checked_add(24));
+        if expected != Some(bytes.len()) {
+            return Err(invalid("render checkpoint does not match its size"));
+        }
+
+        let mut framebuffer = Self::new(cols, rows);
+        framebuffer.samples = samples;
+        if has_aovs {
+            framebuffer.aovs = vec![Aovs::default(); framebuffer.color.len()];
+        }
+        let mut vec3 = || -> io::Result<Vec3> {
+            Ok(Vec3::new(
+                f32::from_le_bytes(next()?),
+                f32::from_le_bytes(next()?),
+                f32::from_le_bytes(next()?),
+            ))
+        };
+        for pixel in 0..framebuffer.color.len() {
+            framebuffer.color[pixel] = vec3()?;
+            if has_aovs {
+                framebuffer.aovs[pixel] = Aovs {
+                    normal: vec3()?,
+                    albedo: vec3()?,
+                    position: vec3()?,
+                    depth: vec3()?,
+                    uv: vec3()?,
+                    material_id: vec3()?,
+                    object_id: vec3()?,
+                };
+            }
+        }
+
+        Ok(framebuffer)
+    }
+}
+
+const CHECKPOINT_MAGIC: &[u8] = b"rtiow checkpoint 2\n";
+
+// renders in passes until framebuffer holds settings.samples samples per
+// pixel or the time limit is up, calling on_pass after every pass and
+// on_progress every settings.progress_interval. rendering into a framebuffer
+// that already holds samples adds to them. returns what this call did
+pub fn render<F: FnMut(&Framebuffer), P: FnMut(&Progress)>(
+    framebuffer: &mut Framebuffer,
+    camera: &(dyn Camera + Sync),
+    world: &(dyn Hittable + Sync),
+    lights: &LightList,
+    settings: &RenderSettings,
+    mut on_pass: F,
+    mut on_progress: P,
+) -> Stats {
+    let start = Instant::now();
+    let mut stats = Stats::default();
+    let pixels = (framebuffer.cols * framebuffer.rows) as u64;
+    let (mut samples_taken, mut rays) = (0, 0);
+    if settings.aovs && framebuffer.aovs.is_empty() {
+        assert_eq!(
+            framebuffer.samples, 0,
+            "framebuffer holds samples without aovs"
+        );
+        framebuffer.aovs = vec![Aovs::default(); pixels as usize];
+    }
+    let mut report = |samples_done: u64, samples: u64, rays: u64| {
+        on_progress(&Progress {
+            samples_done,
+            samples_total: settings.samples as u64 * pixels,
+            samples,
+            rays,
+            elapsed: start.elapsed(),
+            time_limit: settings.time_limit,
+        })
+    };
+
+    while framebuffer.samples < settings.samples {
+        if let Some(limit) = settings.time_limit {
+            if start.elapsed() >= limit {
+                break;
+            }
+        }
+
+        let samples = settings
+            .samples_per_pass
+            .max(1)
+            .min(settings.samples - framebuffer.samples);
+        let done = framebuffer.samples as u64 * pixels;
+        let pass = render_pass(
+            framebuffer,
+            camera,
+            world,
+            lights,
+            settings,
+            samples,
+            |pass_samples, pass_rays| {
+                report(
+                    done + pass_samples,
+                    samples_taken + pass_samples,
+                    rays + pass_rays,
+                )
+            },
+        );
+        framebuffer.samples += samples;
+        samples_taken += samples as u64 * pixels;
+        rays += pass.rays;
+        stats += pass;
+
+        report(framebuffer.samples as u64 * pixels, samples_taken, rays);
+        on_pass(framebuffer);
+    }
+
+    stats
+}
+
+// sums for one row of pixels from a single pass
+struct RowSamples {
+    row: u32,
+    color: Vec<Vec3>,
+    aovs: Vec<Aovs>,
+}
+
+// adds samples more samples to every pixel, rows are dealt out to the
+// threads in turn so they all get a share of the expensive parts. while they
+// work this thread calls report with the camera samples taken and the rays
+// cast so far
+fn render_pass<R: FnMut(u64, u64)>(
+    framebuffer: &mut Framebuffer,
+    camera: &(dyn Camera + Sync),
+    world: &(dyn Hittable + Sync),
+    lights: &LightList,
+    settings: &RenderSettings,
+    samples: u32,
+    mut report: R,
+) -> Stats {
+    let (cols, rows) = (framebuffer.cols, framebuffer.rows);
+    let thread_count = settings.threads.max(1);
+    let (samples_taken, rays) = (AtomicU64::new(0), AtomicU64::new(0));
+    let (samples_taken, rays) = (&samples_taken, &rays);
+    let mut last_report = Instant::now();
+
+    let results: Vec<(Vec<RowSamples>, Stats)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..thread_count)
+            .map(|t| {
+                scope.spawn(move || {
+                    let mut rng = rand::thread_rng();
+                    let mut rows_done = vec![];
+
+                    for j in (t..rows).step_by(thread_count as usize) {
+                        let rays_before = stats::current().rays;
+                        let mut colors = Vec::with_capacity(cols as usize);
+                        let mut aovs = Vec::with_capacity(cols as usize);
+                        for i in 0..cols {
+                            let mut sampled_color_sum = Vec3::new(0.0, 0.0, 0.0);
+                            let mut aov_sum = Aovs::default();
+
+                            for _ in 0..samples {
+                                let u = (i as f32 + rng.gen::<f32>()) / cols as f32;
+                                let v = (rows as f32 - (j as f32 + rng.gen::<f32>())) / rows as f32;
+                                let ray = camera.get_ray(u, v);
+                                if settings.aovs {
+                                    aov_sum += Aovs::first_hit(&ray, world);
+                                }
+                                sampled_color_sum += settings.integrator.radiance(
+                                    ray,
+                                    world,
+                                    lights,
+                                    settings.has_light,
+                                    rng.gen::<f32>(),
+                                );
+                            }
+
+                            colors.push(sampled_color_sum);
+                            if settings.aovs {
+                                aovs.push(aov_sum);
+                            }
+                        }
+                        samples_taken.fetch_add(cols as u64 * samples as u64, Ordering::Relaxed);
+                        rays.fetch_add(stats::current().rays - rays_before, Ordering::Relaxed);
+                        stats::record(|stats| stats.primary_rays += cols as u64 * samples as u64);
+                        rows_done.push(RowSamples {
+                            row: j,
+                            color: colors,
+                            aovs,
+                        });
+                    }
+
+                    (rows_done, stats::take())
+                })
+            })
+            .collect();
+
+        while !handles.iter().all(|h| h.is_finished()) {
+            thread::sleep(settings.progress_interval.min(Duration::from_millis(10)));
+            if last_report.elapsed() >= settings.progress_interval {
+                report(
+                    samples_taken.load(Ordering::Relaxed),
+                    rays.load(Ordering::Relaxed),
+                );
+                last_report = Instant::now();
+            }
+        }
+
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut stats = Stats::default();
+    for (rows_done, thread_stats) in results {
+        stats += thread_stats;
+        for row in rows_done {
+            let start = (row.row * cols) as usize;
+            for (i, c) in row.color.into_iter().enumerate() {
+                framebuffer.color[start + i] += c;
+            }
+            for (i, aov) in row.aovs.into_iter().enumerate() {
+                framebuffer.aovs[start + i] += aov;
+            }
+        }
+    }
+
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framebuffer(aovs: bool) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.samples = 7;
+        for (i, color) in framebuffer.color.iter_mut().enumerate() {
+            *color = Vec3::new(i as f32, 0.5, -1.0);
+        }
+        if aovs {
+            framebuffer.aovs = (0..6)
+                .map(|i| Aovs {
+                    depth: Vec3::new(i as f32, i as f32, i as f32),
+                    object_id: Vec3::new(0.25, 0.5, 0.75),
+                    ..Aovs::default()
+                })
+                .collect();
+        }
+
+        framebuffer
+    }
+
+    fn checkpoint_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rtiow_{}_{}.checkpoint", name, std::process::id()))
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let fingerprint = Fingerprint::new().with_bytes(b"scene");
+        for &aovs in [false, true].iter() {
+            let path = checkpoint_path(if aovs { "aovs" } else { "color" });
+            let saved = framebuffer(aovs);
+            saved.save_checkpoint(&path, fingerprint).unwrap();
+            let loaded = Framebuffer::load_checkpoint(&path, fingerprint).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!((loaded.cols, loaded.rows), (3, 2));
+            assert_eq!(loaded.samples(), 7);
+            for (a, b) in saved.color.iter().zip(loaded.color.iter()) {
+                assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+            }
+            assert_eq!(loaded.aovs.len(), saved.aovs.len());
+            for (a, b) in saved.aovs.iter().zip(loaded.aovs.iter()) {
+                for (a, b) in a.layers().iter().zip(b.layers().iter()) {
+                    assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checkpoint_from_another_render_is_refused() {
+        let path = checkpoint_path("mismatch");
+        framebuffer(false)
+            .save_checkpoint(&path, Fingerprint::new().with_bytes(b"scene"))
+            .unwrap();
+        let loaded = Framebuffer::load_checkpoint(&path, Fingerprint::new().with_bytes(b"other"));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_checkpoint_is_refused() {
+        let path = checkpoint_path("truncated");
+        let fingerprint = Fingerprint::new();
+        framebuffer(true)
+            .save_checkpoint(&path, fingerprint)
+            .unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        let loaded = Framebuffer::load_checkpoint(&path, fingerprint);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
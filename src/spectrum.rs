@@ -0,0 +1,447 @@
+use crate::vec::Vec3;
+use std::ops::{Add, AddAssign, Mul};
+
+// visible range traced by wavelength dependent paths, in nanometers
+pub const WAVELENGTH_MIN: f32 = 380.0;
+pub const WAVELENGTH_MAX: f32 = 780.0;
+
+pub fn sample_wavelength(u: f32) -> f32 {
+    WAVELENGTH_MIN + u * (WAVELENGTH_MAX - WAVELENGTH_MIN)
+}
+
+fn piecewise_gaussian(x: f32, mu: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+    let sigma = if x < mu { sigma_low } else { sigma_high };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 color matching functions, multi-lobe fit from Wyman, Sloan and
+// Shirley 2013 "Simple Analytic Approximations to the CIE XYZ Color Matching
+// Functions"
+pub fn cie_xyz(wavelength: f32) -> Vec3 {
+    let g = |mu, sigma_low, sigma_high| piecewise_gaussian(wavelength, mu, sigma_low, sigma_high);
+
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// XYZ to linear sRGB with a D65 white point
+pub fn xyz_to_rgb(xyz: &Vec3) -> Vec3 {
+    Vec3::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+// integral of the y matching function over the traced range
+const CIE_Y_INTEGRAL: f32 = 106.919_73;
+
+// mean of the clamped rgb response over the traced range, precomputed so a
+// uniformly sampled wavelength averages out to white
+const RGB_RESPONSE_MEAN: (f32, f32, f32) = (0.440_457, 0.288_466, 0.273_240);
+
+// rgb weight carried by a path that was narrowed down to a single uniformly
+// sampled wavelength, out of gamut negative responses are clamped
+pub fn wavelength_to_rgb(wavelength: f32) -> Vec3 {
+    let rgb = xyz_to_rgb(&cie_xyz(wavelength)).clamp(0.0, f32::MAX);
+
+    Vec3::new(
+        rgb.x / RGB_RESPONSE_MEAN.0,
+        rgb.y / RGB_RESPONSE_MEAN.1,
+        rgb.z / RGB_RESPONSE_MEAN.2,
+    )
+}
+
+// wavelength dependent index of refraction, coefficients take the wavelength
+// in micrometers as is conventional for glass catalogs
+#[derive(Clone, Copy, Debug)]
+pub enum Ior {
+    Constant(f32),
+    // n = a + b / lambda^2
+    Cauchy { a: f32, b: f32 },
+    // n^2 = 1 + sum b_i lambda^2 / (lambda^2 - c_i)
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    // schott N-BK7 crown glass
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_5],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65],
+    };
+
+    // dense flint glass, much more dispersive than BK7
+    pub const SF11: Ior = Ior::Sellmeier {
+        b: [1.737_596_9, 0.313_747_35, 1.898_781],
+        c: [0.013_188_707, 0.062_306_814, 155.236_3],
+    };
+
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let micrometers = wavelength / 1000.0;
+        let squared = micrometers * micrometers;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy { a, b } => a + b / squared,
+            Ior::Sellmeier { b, c } => {
+                let sum: f32 = b
+                    .iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * squared / (squared - c))
+                    .sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    // index at the helium d-line, used when a path carries no wavelength
+    pub fn nominal(&self) -> f32 {
+        self.at(587.6)
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+// reflectance of a thin dielectric film between two media, summing every
+// internal reflection (airy), averaged over s and p polarization. n1 is the
+// incident medium, n2 the film and n3 the medium behind it
+pub fn thin_film_reflectance(
+    cos_theta_1: f32,
+    n1: f32,
+    n2: f32,
+    n3: f32,
+    thickness: f32,
+    wavelength: f32,
+) -> f32 {
+    let cos_theta_1 = cos_theta_1.clamp(0.0, 1.0);
+    let sin2_theta_1 = 1.0 - cos_theta_1 * cos_theta_1;
+
+    let sin2_theta_2 = sin2_theta_1 * (n1 / n2) * (n1 / n2);
+    let sin2_theta_3 = sin2_theta_1 * (n1 / n3) * (n1 / n3);
+    if sin2_theta_2 >= 1.0 || sin2_theta_3 >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_2 = (1.0 - sin2_theta_2).sqrt();
+    let cos_theta_3 = (1.0 - sin2_theta_3).sqrt();
+
+    let phase = 4.0 * std::f32::consts::PI * n2 * thickness * cos_theta_2 / wavelength;
+    let airy = |r12: f32, r23: f32| {
+        let cross = 2.0 * r12 * r23 * phase.cos();
+        (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+    };
+
+    let s = airy(
+        (n1 * cos_theta_1 - n2 * cos_theta_2) / (n1 * cos_theta_1 + n2 * cos_theta_2),
+        (n2 * cos_theta_2 - n3 * cos_theta_3) / (n2 * cos_theta_2 + n3 * cos_theta_3),
+    );
+    let p = airy(
+        (n2 * cos_theta_1 - n1 * cos_theta_2) / (n2 * cos_theta_1 + n1 * cos_theta_2),
+        (n3 * cos_theta_2 - n2 * cos_theta_3) / (n3 * cos_theta_2 + n2 * cos_theta_3),
+    );
+
+    0.5 * (s + p)
+}
+
+// number of wavelengths traced together along one path
+pub const SPECTRAL_SAMPLES: usize = 4;
+
+// hero wavelength sampling, Wilkie et al. 2014, one uniformly sampled hero
+// wavelength plus the others rotated evenly through the range so they all
+// share the path built for the hero
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    pub lambda: [f32; SPECTRAL_SAMPLES],
+    pdf: [f32; SPECTRAL_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample(u: f32) -> Self {
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        let hero = sample_wavelength(u);
+        let mut lambda = [hero; SPECTRAL_SAMPLES];
+        for (i, wavelength) in lambda.iter_mut().enumerate().skip(1) {
+            let mut rotated = hero + i as f32 * range / SPECTRAL_SAMPLES as f32;
+            if rotated > WAVELENGTH_MAX {
+                rotated -= range;
+            }
+            *wavelength = rotated;
+        }
+
+        Self {
+            lambda,
+            pdf: [1.0 / range; SPECTRAL_SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f32 {
+        self.lambda[0]
+    }
+
+    // a wavelength dependent bounce can only follow the hero, the others
+    // are dropped and the hero carries the whole estimate
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SPECTRAL_SAMPLES as f32;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|pdf| *pdf == 0.0)
+    }
+
+    // monte carlo estimate of the linear sRGB color of the sampled radiance
+    pub fn to_rgb(&self, radiance: &SampledSpectrum) -> Vec3 {
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..SPECTRAL_SAMPLES {
+            if self.pdf[i] == 0.0 {
+                continue;
+            }
+            xyz += cie_xyz(self.lambda[i]) * (radiance.values[i] / self.pdf[i]);
+        }
+        xyz *= 1.0 / (SPECTRAL_SAMPLES as f32 * CIE_Y_INTEGRAL);
+
+        xyz_to_rgb(&xyz)
+    }
+}
+
+// radiance or throughput at each of the sampled wavelengths
+#[derive(Clone, Copy, Debug)]
+pub struct SampledSpectrum {
+    pub values: [f32; SPECTRAL_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(value: f32) -> Self {
+        Self {
+            values: [value; SPECTRAL_SAMPLES],
+        }
+    }
+
+    pub fn from_fn<F: Fn(f32) -> f32>(wavelengths: &SampledWavelengths, f: F) -> Self {
+        let mut values = [0.0; SPECTRAL_SAMPLES];
+        for (value, wavelength) in values.iter_mut().zip(wavelengths.lambda.iter()) {
+            *value = f(*wavelength);
+        }
+
+        Self { values }
+    }
+
+    // surface colors and throughput weights
+    pub fn from_rgb_reflectance(rgb: &Vec3, wavelengths: &SampledWavelengths) -> Self {
+        Self::from_fn(wavelengths, |wavelength| {
+            rgb_to_reflectance(rgb, wavelength)
+        })
+    }
+
+    // light colors, white maps to D65 which is the sRGB white point
+    pub fn from_rgb_illuminant(rgb: &Vec3, wavelengths: &SampledWavelengths) -> Self {
+        Self::from_fn(wavelengths, |wavelength| {
+            rgb_to_reflectance(rgb, wavelength) * d65(wavelength) / D65_LUMINANCE
+        })
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.values.iter().all(|value| *value == 0.0)
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut values = self.values;
+        for (value, other) in values.iter_mut().zip(other.values.iter()) {
+            *value += other;
+        }
+        Self { values }
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut values = self.values;
+        for (value, other) in values.iter_mut().zip(other.values.iter()) {
+            *value *= other;
+        }
+        Self { values }
+    }
+}
+
+impl Mul<f32> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, scalar: f32) -> Self {
+        let mut values = self.values;
+        for value in values.iter_mut() {
+            *value *= scalar;
+        }
+        Self { values }
+    }
+}
+
+// basis spectra from Smits 1999 "An RGB to Spectrum Conversion for
+// Reflectances", sampled at 10 evenly spaced points from 380 to 720nm
+const SMITS_WHITE: [f32; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f32; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f32; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f32; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f32; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f32; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f32; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// linear interpolation of evenly spaced samples, clamped at both ends
+fn interpolate(table: &[f32], start: f32, end: f32, wavelength: f32) -> f32 {
+    let last = table.len() - 1;
+    let t = (wavelength - start) / (end - start) * last as f32;
+    if t <= 0.0 {
+        return table[0];
+    }
+    if t >= last as f32 {
+        return table[last];
+    }
+    let i = t as usize;
+    let f = t - i as f32;
+
+    table[i] * (1.0 - f) + table[i + 1] * f
+}
+
+// smits upsampling of an rgb color to its spectral value at wavelength,
+// colors brighter than one are scaled down and back up again
+pub fn rgb_to_reflectance(rgb: &Vec3, wavelength: f32) -> f32 {
+    let scale = rgb.x.max(rgb.y).max(rgb.z);
+    if scale <= 0.0 {
+        return 0.0;
+    }
+    let (r, g, b) = if scale > 1.0 {
+        (rgb.x / scale, rgb.y / scale, rgb.z / scale)
+    } else {
+        (rgb.x, rgb.y, rgb.z)
+    };
+    let basis = |table: &[f32]| interpolate(table, 380.0, 720.0, wavelength);
+
+    let value = if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    };
+
+    value.max(0.0) * scale.max(1.0)
+}
+
+// CIE standard illuminant D65, 380 to 780nm in 10nm steps
+const D65: [f32; 41] = [
+    49.98, 54.65, 82.75, 91.49, 93.43, 86.68, 104.86, 117.01, 117.81, 114.86, 115.92, 108.81,
+    109.35, 107.80, 104.79, 107.69, 104.41, 104.05, 100.00, 96.33, 95.79, 88.69, 90.01, 89.60,
+    87.70, 83.29, 83.70, 80.03, 80.21, 82.28, 78.28, 69.72, 71.61, 74.35, 61.60, 69.89, 75.09,
+    63.59, 46.42, 66.81, 63.38,
+];
+
+// luminance of the D65 table over the traced range
+const D65_LUMINANCE: f32 = 98.853_11;
+
+pub fn d65(wavelength: f32) -> f32 {
+    interpolate(&D65, 380.0, 780.0, wavelength)
+}
+
+// planck's law for a black body at temperature in kelvin, scaled so the
+// peak is one
+pub fn blackbody(wavelength: f32, temperature: f32) -> f32 {
+    let planck = |wavelength: f32| {
+        let c = 299_792_458.0_f64;
+        let h = 6.626_070_15e-34_f64;
+        let kb = 1.380_649e-23_f64;
+        let l = wavelength as f64 * 1e-9;
+        (2.0 * h * c * c) / (l.powi(5) * (((h * c) / (l * kb * temperature as f64)).exp() - 1.0))
+    };
+    // wien's displacement law gives the peak wavelength
+    let peak = 2.897_772e6 / temperature;
+
+    (planck(wavelength) / planck(peak)) as f32
+}
+
+// spectral illuminants that lights can emit in place of an rgb color
+#[derive(Clone, Copy, Debug)]
+pub enum Illuminant {
+    D65,
+    // temperature in kelvin
+    Blackbody(f32),
+}
+
+impl Illuminant {
+    // relative spectral power
+    pub fn at(&self, wavelength: f32) -> f32 {
+        match self {
+            Illuminant::D65 => d65(wavelength),
+            Illuminant::Blackbody(temperature) => blackbody(wavelength, *temperature),
+        }
+    }
+
+    pub fn luminance(&self) -> f32 {
+        integrate_xyz(|wavelength| self.at(wavelength)).y
+    }
+
+    // linear sRGB of the illuminant for the rgb integrator
+    pub fn to_rgb(&self) -> Vec3 {
+        xyz_to_rgb(&integrate_xyz(|wavelength| self.at(wavelength)))
+    }
+}
+
+// XYZ of a spectrum by integrating over the traced range in 1nm steps
+fn integrate_xyz<F: Fn(f32) -> f32>(spectrum: F) -> Vec3 {
+    let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+    let mut wavelength = WAVELENGTH_MIN + 0.5;
+    while wavelength < WAVELENGTH_MAX {
+        xyz += cie_xyz(wavelength) * spectrum(wavelength);
+        wavelength += 1.0;
+    }
+
+    xyz * (1.0 / CIE_Y_INTEGRAL)
+}
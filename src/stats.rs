@@ -0,0 +1,132 @@
+use std::cell::Cell;
+use std::fmt;
+use std::ops::AddAssign;
+
+// counters for what the renderer did, each thread keeps its own and they are
+// summed once its work is done
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub primary_rays: u64,
+    // every ray cast into the scene, camera, bounce, shadow and aov rays
+    pub rays: u64,
+    // ray against primitive tests
+    pub intersection_tests: u64,
+    // there is no acceleration structure yet so this stays zero until a
+    // bvh counts its visits
+    pub bvh_nodes_visited: u64,
+    pub paths: u64,
+    // rays traced along every path, divide by paths for the average length
+    pub path_segments: u64,
+    pub terminated_by_depth: u64,
+    pub terminated_by_absorption: u64,
+    pub terminated_by_escape: u64,
+}
+
+thread_local! {
+    static COUNTERS: Cell<Stats> = Cell::new(Stats::default());
+}
+
+// updates the counters of the current thread
+pub fn record<F: FnOnce(&mut Stats)>(f: F) {
+    COUNTERS.with(|counters| {
+        let mut stats = counters.get();
+        f(&mut stats);
+        counters.set(stats);
+    });
+}
+
+// the counters of the current thread so far
+pub fn current() -> Stats {
+    COUNTERS.with(|counters| counters.get())
+}
+
+// the counters of the current thread, which start again from zero
+pub fn take() -> Stats {
+    COUNTERS.with(|counters| counters.replace(Stats::default()))
+}
+
+// path terminations, recorded by the integrators along with the length of
+// the path that ended
+#[derive(Clone, Copy, Debug)]
+pub enum Termination {
+    Depth,
+    Absorption,
+    Escape,
+}
+
+pub fn record_path(termination: Termination, segments: u32) {
+    record(|stats| {
+        stats.paths += 1;
+        stats.path_segments += segments as u64;
+        match termination {
+            Termination::Depth => stats.terminated_by_depth += 1,
+            Termination::Absorption => stats.terminated_by_absorption += 1,
+            Termination::Escape => stats.terminated_by_escape += 1,
+        }
+    });
+}
+
+impl Stats {
+    pub fn average_path_length(&self) -> f64 {
+        self.path_segments as f64 / self.paths.max(1) as f64
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\n",
+                "  \"primary_rays\": {},\n",
+                "  \"rays\": {},\n",
+                "  \"intersection_tests\": {},\n",
+                "  \"bvh_nodes_visited\": {},\n",
+                "  \"paths\": {},\n",
+                "  \"average_path_length\": {},\n",
+                "  \"terminated_by_depth\": {},\n",
+                "  \"terminated_by_absorption\": {},\n",
+                "  \"terminated_by_escape\": {}\n",
+                "}}\n"
+            ),
+            self.primary_rays,
+            self.rays,
+            self.intersection_tests,
+            self.bvh_nodes_visited,
+            self.paths,
+            self.average_path_length(),
+            self.terminated_by_depth,
+            self.terminated_by_absorption,
+            self.terminated_by_escape,
+        )
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.primary_rays += other.primary_rays;
+        self.rays += other.rays;
+        self.intersection_tests += other.intersection_tests;
+        self.bvh_nodes_visited += other.bvh_nodes_visited;
+        self.paths += other.paths;
+        self.path_segments += other.path_segments;
+        self.terminated_by_depth += other.terminated_by_depth;
+        self.terminated_by_absorption += other.terminated_by_absorption;
+        self.terminated_by_escape += other.terminated_by_escape;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let share = |n: u64| 100.0 * n as f64 / self.paths.max(1) as f64;
+        writeln!(f, "primary rays:        {}", self.primary_rays)?;
+        writeln!(f, "rays:                {}", self.rays)?;
+        writeln!(f, "intersection tests:  {}", self.intersection_tests)?;
+        writeln!(f, "bvh nodes visited:   {}", self.bvh_nodes_visited)?;
+        writeln!(f, "average path length: {:.2}", self.average_path_length())?;
+        writeln!(
+            f,
+            "paths ended by depth {:.1}%, absorption {:.1}%, escape {:.1}%",
+            share(self.terminated_by_depth),
+            share(self.terminated_by_absorption),
+            share(self.terminated_by_escape)
+        )
+    }
+}
@@ -1,6 +1,6 @@
-use std::ops::{Add, AddAssign, Mul, MulAssign, Sub, SubAssign};
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -50,6 +50,15 @@ impl Vec3 {
         }
     }
 
+    #[inline]
+    pub fn exp(&self) -> Self {
+        Self {
+            x: self.x.exp(),
+            y: self.y.exp(),
+            z: self.z.exp(),
+        }
+    }
+
     #[inline]
     pub fn reflect(&self, orthogonal_unit_vector: &Self) -> Self {
         *self - (2.0 * (self.dot(orthogonal_unit_vector) * *orthogonal_unit_vector))
@@ -214,3 +223,15 @@ impl MulAssign<f32> for Vec3 {
         }
     }
 }
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::medium::HenyeyGreenstein;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vec::Vec3;
+
+use rand::Rng;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// dense grid of scalar values covering the unit cube, x varies fastest
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f32>,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>) -> Self {
+        assert!(nx > 0 && ny > 0 && nz > 0, "voxel grid has no voxels");
+        assert_eq!(
+            Some(data.len()),
+            nx.checked_mul(ny).and_then(|n| n.checked_mul(nz)),
+            "voxel data does not match grid size"
+        );
+        assert!(
+            data.iter().all(|v| *v >= 0.0 && v.is_finite()),
+            "voxel values must be finite and not negative"
+        );
+        Self { nx, ny, nz, data }
+    }
+
+    // fills the grid from a function of the voxel center in the unit cube
+    pub fn from_fn<F: Fn(Vec3) -> f32>(nx: usize, ny: usize, nz: usize, f: F) -> Self {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    data.push(f(Vec3::new(
+                        (x as f32 + 0.5) / nx as f32,
+                        (y as f32 + 0.5) / ny as f32,
+                        (z as f32 + 0.5) / nz as f32,
+                    )));
+                }
+            }
+        }
+
+        Self::new(nx, ny, nz, data)
+    }
+
+    // reads a raw grid, an ascii header line "nx ny nz" followed by
+    // nx * ny * nz little endian f32 values
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let newline = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| invalid("missing voxel grid header"))?;
+        let header = std::str::from_utf8(&bytes[..newline])
+            .map_err(|_| invalid("voxel grid header is not utf-8"))?;
+        let dims = header
+            .split_whitespace()
+            .map(|d| d.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("voxel grid header is not three sizes"))?;
+        if dims.len() != 3 {
+            return Err(invalid("voxel grid header is not three sizes"));
+        }
+        if dims.contains(&0) {
+            return Err(invalid("voxel grid has no voxels"));
+        }
+
+        let body = &bytes[newline + 1..];
+        let size = dims[0]
+            .checked_mul(dims[1])
+            .and_then(|n| n.checked_mul(dims[2]))
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| invalid("voxel grid is too large"))?;
+        if body.len() != size {
+            return Err(invalid("voxel data does not match grid size"));
+        }
+        let data: Vec<f32> = body
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        // tracking needs a majorant, negative or nan values break it
+        if !data.iter().all(|v| *v >= 0.0 && v.is_finite()) {
+            return Err(invalid("voxel values must be finite and not negative"));
+        }
+
+        Ok(Self::new(dims[0], dims[1], dims[2], data))
+    }
+
+    pub fn max_value(&self) -> f32 {
+        self.data.iter().cloned().fold(0.0, f32::max)
+    }
+
+    fn at(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.data[(z * self.ny + y) * self.nx + x]
+    }
+
+    // trilinear lookup between voxel centers, p in the unit cube
+    pub fn lookup(&self, p: Vec3) -> f32 {
+        let axis = |p: f32, n: usize| {
+            let x = (p * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            let i = (x as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), x - i as f32)
+        };
+        let (x0, x1, fx) = axis(p.x, self.nx);
+        let (y0, y1, fy) = axis(p.y, self.ny);
+        let (z0, z1, fz) = axis(p.z, self.nz);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.at(x0, y0, z), self.at(x1, y0, z), fx),
+                lerp(self.at(x0, y1, z), self.at(x1, y1, z), fx),
+                fy,
+            )
+        };
+
+        lerp(plane(z0), plane(z1), fz)
+    }
+}
+
+// axis aligned placement of a grid in the world
+#[derive(Clone, Copy)]
+struct Bounds {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Bounds {
+    fn local(&self, point: Vec3) -> Vec3 {
+        (point - self.min).make_comp_div(&(self.max - self.min))
+    }
+
+    // parametric range of the ray inside the box, if any
+    fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        let axes = [
+            (ray.origin.x, ray.direction.x, self.min.x, self.max.x),
+            (ray.origin.y, ray.direction.y, self.min.y, self.max.y),
+            (ray.origin.z, ray.direction.z, self.min.z, self.max.z),
+        ];
+        for &(origin, direction, min, max) in axes.iter() {
+            let inv = 1.0 / direction;
+            let (near, far) = if inv < 0.0 {
+                ((max - origin) * inv, (min - origin) * inv)
+            } else {
+                ((min - origin) * inv, (max - origin) * inv)
+            };
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t1 <= t0 {
+                return None;
+            }
+        }
+
+        Some((t0, t1))
+    }
+}
+
+// scattering inside a volume, the phase function takes the place of a bsdf
+// and emission is looked up from an optional grid for fire
+pub struct VolumeMaterial {
+    phase: HenyeyGreenstein,
+    albedo: Vec3,
+    emission: Option<(VoxelGrid, Vec3)>,
+    bounds: Bounds,
+}
+
+impl Material for VolumeMaterial {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vec3)> {
+        let direction = self.phase.sample(&ray.direction);
+        Some((Ray::new(hit.point, direction), self.albedo))
+    }
+
+    fn eval(&self, ray: &Ray, _hit: &HitRecord, direction: &Vec3) -> Vec3 {
+        let cos_theta = ray
+            .direction
+            .make_unit_vector()
+            .dot(&direction.make_unit_vector());
+        self.albedo * self.phase.eval(cos_theta)
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Vec3 {
+        match &self.emission {
+            Some((grid, color)) => *color * grid.lookup(self.bounds.local(hit.point)),
+            None => Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// heterogeneous participating medium filling a box, density is the grid value
+// times the extinction scale
+pub struct VoxelVolume {
+    density: VoxelGrid,
+    sigma_t: f32,
+    majorant: f32,
+    material: VolumeMaterial,
+}
+
+impl VoxelVolume {
+    pub fn new(density: VoxelGrid, min: Vec3, max: Vec3, sigma_t: f32) -> Self {
+        let majorant = density.max_value() * sigma_t;
+        Self {
+            density,
+            sigma_t,
+            majorant,
+            material: VolumeMaterial {
+                phase: HenyeyGreenstein::new(0.0),
+                albedo: Vec3::new(1.0, 1.0, 1.0),
+                emission: None,
+                bounds: Bounds { min, max },
+            },
+        }
+    }
+
+    // fraction of each collision that scatters rather than absorbs
+    pub fn with_albedo(mut self, albedo: Vec3) -> Self {
+        self.material.albedo = albedo;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, g: f32) -> Self {
+        self.material.phase = HenyeyGreenstein::new(g);
+        self
+    }
+
+    // emitted radiance at a collision is the grid value times color
+    pub fn with_emission(mut self, emission: VoxelGrid, color: Vec3) -> Self {
+        self.material.emission = Some((emission, color));
+        self
+    }
+
+    fn sigma_t(&self, point: Vec3) -> f32 {
+        self.density.lookup(self.material.bounds.local(point)) * self.sigma_t
+    }
+}
+
+impl Hittable for VoxelVolume {
+    // delta tracking, steps through the box by the majorant and accepts a
+    // tentative collision with probability sigma_t over the majorant
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::record(|stats| stats.intersection_tests += 1);
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (t0, t1) = self.material.bounds.intersect(ray, t_min, t_max)?;
+
+        let mut rng = rand::thread_rng();
+        let rate = self.majorant * ray.direction.magnitude();
+        let mut t = t0;
+        loop {
+            t -= (1.0 - rng.gen::<f32>()).ln() / rate;
+            if t >= t1 {
+                return None;
+            }
+            let point = ray.point_at_parameter(t);
+            if rng.gen::<f32>() * self.majorant < self.sigma_t(point) {
+                let normal = -ray.direction.make_unit_vector();
+                return Some(HitRecord::new(t, point, normal, &self.material));
+            }
+        }
+    }
+
+    // ratio tracking, multiplies in the chance of each tentative collision
+    // being null
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        stats::record(|stats| stats.intersection_tests += 1);
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+        let (t0, t1) = match self.material.bounds.intersect(ray, t_min, t_max) {
+            Some(range) => range,
+            None => return 1.0,
+        };
+
+        let mut rng = rand::thread_rng();
+        let rate = self.majorant * ray.direction.magnitude();
+        let mut transmittance = 1.0;
+        let mut t = t0;
+        loop {
+            t -= (1.0 - rng.gen::<f32>()).ln() / rate;
+            if t >= t1 {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.sigma_t(ray.point_at_parameter(t)) / self.majorant;
+            if transmittance <= 0.0 {
+                return 0.0;
+            }
+        }
+    }
+}